@@ -1,24 +1,171 @@
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{Device, FromSample, Sample, SampleFormat, Stream, StreamConfig};
+use cpal::traits::{DeviceTrait, HostTrait};
+use cpal::{
+    BufferSize, Device, DeviceId, FromSample, Sample, SampleFormat, StreamConfig,
+    SupportedBufferSize, SupportedStreamConfig, SupportedStreamConfigRange,
+};
+use realfft::num_complex::Complex;
+use realfft::RealFftPlanner;
+use rtrb::{Consumer, Producer, RingBuffer};
 use rubato::{Fft, FixedSync, Resampler};
-use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
-use std::sync::{Arc, Mutex};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::Duration;
 
 use crate::error::{Error, Result};
+use crate::meter::{Metering, METER_WINDOW_LEN};
+use crate::source::{AudioSource, CpalSource};
+use crate::spill::SpillFile;
 use crate::transcribe::WHISPER_SAMPLE_RATE;
 
 /// Helper to get device name from description
-fn get_device_name(device: &Device) -> String {
+pub fn get_device_name(device: &Device) -> String {
     device
         .description()
         .map_or_else(|_| "Unknown".to_string(), |d| d.name().to_string())
 }
 
-/// Returns a list of available audio input device names.
-pub fn list_input_devices() -> Vec<String> {
+/// Helper to get the stable identifier of a device, e.g. `coreaudio:<uid>`
+fn get_device_id(device: &Device) -> Option<String> {
+    device.id().ok().map(|id| id.to_string())
+}
+
+/// Id a device is listed under. A device whose id can't be read is listed by its
+/// display name, which the device lookups also accept, rather than hidden.
+fn listed_device_id(device: &Device) -> String {
+    match device.id() {
+        Ok(id) => id.to_string(),
+        Err(e) => {
+            let name = get_device_name(device);
+            eprintln!("[Failed to get id of device '{name}', listing it by name: {e}]");
+            name
+        }
+    }
+}
+
+/// Sample rates probed when listing what a device supports
+const COMMON_SAMPLE_RATES: [u32; 8] = [8000, 16000, 22050, 32000, 44100, 48000, 96000, 192_000];
+
+/// Sample formats the capture and playback streams can be built with
+pub const SUPPORTED_SAMPLE_FORMATS: [SampleFormat; 7] = [
+    SampleFormat::F32,
+    SampleFormat::F64,
+    SampleFormat::I8,
+    SampleFormat::I16,
+    SampleFormat::I32,
+    SampleFormat::U8,
+    SampleFormat::U16,
+];
+
+/// Parse a sample format name as cpal displays it, e.g. `i16`.
+pub fn parse_sample_format(name: &str) -> Option<SampleFormat> {
+    SUPPORTED_SAMPLE_FORMATS
+        .iter()
+        .copied()
+        .find(|format| format.to_string() == name)
+}
+
+/// Whether a device is listed for capture or for playback
+#[derive(Clone, Copy)]
+enum DeviceKind {
+    Input,
+    Output,
+}
+
+/// An audio device as presented in settings
+#[derive(Debug, Clone, Serialize)]
+pub struct AudioDeviceInfo {
+    pub id: String,
+    pub name: String,
+    pub is_default: bool,
+    pub sample_rates: Vec<u32>,
+    pub channels: Vec<u16>,
+    /// Channels used with the device's default config
+    pub channel_count: u16,
+    /// Supported sample formats, e.g. `f32`
+    pub sample_formats: Vec<String>,
+    /// Range of buffer sizes in frames, if the device reports one
+    pub buffer_size_min: Option<u32>,
+    pub buffer_size_max: Option<u32>,
+}
+
+impl AudioDeviceInfo {
+    fn from_device(
+        device: &Device,
+        id: String,
+        default_id: Option<&str>,
+        kind: DeviceKind,
+    ) -> Self {
+        let configs: Vec<SupportedStreamConfigRange> = match kind {
+            DeviceKind::Input => device.supported_input_configs().map(Iterator::collect),
+            DeviceKind::Output => device.supported_output_configs().map(Iterator::collect),
+        }
+        .unwrap_or_default();
+        let default_config = match kind {
+            DeviceKind::Input => device.default_input_config(),
+            DeviceKind::Output => device.default_output_config(),
+        };
+
+        let mut sample_rates = Vec::new();
+        let mut channels = Vec::new();
+        let mut sample_formats = Vec::new();
+        let mut buffer_size: Option<(u32, u32)> = None;
+        for range in configs {
+            channels.push(range.channels());
+            if SUPPORTED_SAMPLE_FORMATS.contains(&range.sample_format()) {
+                sample_formats.push(range.sample_format());
+            }
+            if let SupportedBufferSize::Range { min, max } = *range.buffer_size() {
+                buffer_size =
+                    Some(buffer_size.map_or((min, max), |(lo, hi)| (lo.min(min), hi.max(max))));
+            }
+            sample_rates.extend(
+                COMMON_SAMPLE_RATES.iter().copied().filter(|rate| {
+                    (range.min_sample_rate()..=range.max_sample_rate()).contains(rate)
+                }),
+            );
+        }
+        sample_rates.sort_unstable();
+        sample_rates.dedup();
+        channels.sort_unstable();
+        channels.dedup();
+        sample_formats.sort_unstable();
+        sample_formats.dedup();
+        let channel_count =
+            default_config.map_or_else(|_| channels.last().copied().unwrap_or(1), |c| c.channels());
+
+        Self {
+            is_default: default_id == Some(id.as_str()),
+            name: get_device_name(device),
+            id,
+            sample_rates,
+            channels,
+            channel_count,
+            sample_formats: sample_formats.iter().map(ToString::to_string).collect(),
+            buffer_size_min: buffer_size.map(|(min, _)| min),
+            buffer_size_max: buffer_size.map(|(_, max)| max),
+        }
+    }
+}
+
+/// Returns the available audio input devices.
+pub fn list_input_devices() -> Vec<AudioDeviceInfo> {
     let host = cpal::default_host();
+    let default_id = host.default_input_device().and_then(|d| get_device_id(&d));
     match host.input_devices() {
-        Ok(devices) => devices.map(|d| get_device_name(&d)).collect(),
+        Ok(devices) => devices
+            .map(|d| {
+                AudioDeviceInfo::from_device(
+                    &d,
+                    listed_device_id(&d),
+                    default_id.as_deref(),
+                    DeviceKind::Input,
+                )
+            })
+            .collect(),
         Err(e) => {
             eprintln!("[Failed to enumerate input devices: {e}]");
             Vec::new()
@@ -26,50 +173,175 @@ pub fn list_input_devices() -> Vec<String> {
     }
 }
 
-/// Checks if an audio device with the given name exists.
-/// Returns true if `device_name` is None (system default) or if the device is found.
-pub fn device_exists(device_name: Option<&str>) -> bool {
-    let Some(name) = device_name else {
-        return true; // None means system default, always valid
-    };
+/// Returns the available audio output devices.
+pub fn list_output_devices() -> Vec<AudioDeviceInfo> {
+    let host = cpal::default_host();
+    let default_id = host.default_output_device().and_then(|d| get_device_id(&d));
+    match host.output_devices() {
+        Ok(devices) => devices
+            .map(|d| {
+                AudioDeviceInfo::from_device(
+                    &d,
+                    listed_device_id(&d),
+                    default_id.as_deref(),
+                    DeviceKind::Output,
+                )
+            })
+            .collect(),
+        Err(e) => {
+            eprintln!("[Failed to enumerate output devices: {e}]");
+            Vec::new()
+        }
+    }
+}
 
-    if name.is_empty() {
-        return true; // Empty string treated as system default
+/// Finds an output device by id, or by name for devices listed without one,
+/// falling back to the default output if not found.
+pub fn find_output_device(device_id: Option<&str>) -> Result<Device> {
+    let host = cpal::default_host();
+
+    if let Some(id) = device_id.filter(|id| !id.is_empty()) {
+        if let Some(device) = id
+            .parse::<DeviceId>()
+            .ok()
+            .and_then(|id| host.device_by_id(&id))
+            .or_else(|| {
+                host.output_devices()
+                    .ok()?
+                    .find(|d| get_device_name(d) == id)
+            })
+        {
+            return Ok(device);
+        }
+        eprintln!("[Output device '{id}' not found, falling back to default]");
     }
 
+    host.default_output_device()
+        .ok_or_else(|| Error::Audio("no output device available".to_string()))
+}
+
+/// Whether an input device is a PulseAudio/PipeWire monitor of an output, e.g.
+/// `alsa_output.pci-0000_00_1f.3.analog-stereo.monitor`.
+fn is_monitor_device(device: &Device) -> bool {
+    get_device_name(device).ends_with(".monitor")
+        || get_device_id(device).is_some_and(|id| id.ends_with(".monitor"))
+}
+
+/// Returns the devices system audio can be recorded from: monitor inputs, and on
+/// macOS and Windows the output devices, which cpal records in loopback.
+pub fn list_loopback_devices() -> Vec<AudioDeviceInfo> {
     let host = cpal::default_host();
-    if let Ok(devices) = host.input_devices() {
-        for device in devices {
-            if get_device_name(&device) == name {
-                return true;
-            }
+    let mut devices: Vec<AudioDeviceInfo> = host
+        .input_devices()
+        .map(|devices| {
+            devices
+                .filter(is_monitor_device)
+                .map(|d| {
+                    AudioDeviceInfo::from_device(&d, listed_device_id(&d), None, DeviceKind::Input)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    if cfg!(any(target_os = "macos", target_os = "windows")) {
+        devices.extend(list_output_devices());
+    }
+    devices
+}
+
+/// Finds the device to record system audio from: the requested one (by id, or by name
+/// for devices listed without one), else the first monitor input, else the default output.
+pub fn find_loopback_device(device_id: Option<&str>) -> Result<Device> {
+    let host = cpal::default_host();
+
+    if let Some(id) = device_id.filter(|id| !id.is_empty()) {
+        if let Some(device) = id
+            .parse::<DeviceId>()
+            .ok()
+            .and_then(|id| host.device_by_id(&id))
+            .or_else(|| {
+                let inputs = host.input_devices().into_iter().flatten();
+                let outputs = host.output_devices().into_iter().flatten();
+                inputs.chain(outputs).find(|d| get_device_name(d) == id)
+            })
+        {
+            return Ok(device);
         }
+        eprintln!("[Loopback device '{id}' not found, falling back to default]");
+    }
+
+    if let Some(device) = host
+        .input_devices()
+        .ok()
+        .and_then(|mut devices| devices.find(is_monitor_device))
+    {
+        return Ok(device);
+    }
+    host.default_output_device()
+        .ok_or_else(|| Error::Audio("no loopback device available".to_string()))
+}
+
+/// Whether a stored device setting is a stable id rather than a legacy display name.
+pub fn is_device_id(value: &str) -> bool {
+    value.parse::<DeviceId>().is_ok()
+}
+
+/// Look up the stable id of a device stored by its display name in older settings.
+/// Returns None if no connected device has that name.
+pub fn migrate_device_name(name: &str) -> Option<String> {
+    let host = cpal::default_host();
+    let devices = host.input_devices().ok()?;
+    let id = devices
+        .filter(|d| get_device_name(d) == name)
+        .find_map(|d| get_device_id(&d))?;
+    eprintln!("[Migrated audio device setting '{name}' to id {id}]");
+    Some(id)
+}
+
+/// Find a connected input device by stable id, or by display name for settings not yet migrated.
+fn lookup_device(host: &cpal::Host, device_id: &str) -> Option<Device> {
+    if let Ok(id) = device_id.parse::<DeviceId>() {
+        return host.device_by_id(&id);
+    }
+    host.input_devices()
+        .ok()?
+        .find(|d| get_device_name(d) == device_id)
+}
+
+/// Checks if an audio device with the given id exists.
+/// Returns true if `device_id` is None (system default) or if the device is found.
+pub fn device_exists(device_id: Option<&str>) -> bool {
+    let Some(id) = device_id else {
+        return true; // None means system default, always valid
+    };
+
+    if id.is_empty() {
+        return true; // Empty string treated as system default
     }
-    false
+
+    lookup_device(&cpal::default_host(), id).is_some()
 }
 
-/// Finds an input device by name, falling back to default if not found.
-fn find_device_by_name(device_name: Option<&str>) -> Result<Device> {
+/// Finds an input device by id, falling back to default if not found.
+/// The returned flag is true if a specific device was requested but not found.
+pub fn find_device(device_id: Option<&str>) -> Result<(Device, bool)> {
     let host = cpal::default_host();
+    let mut is_fallback = false;
 
-    match device_name {
+    match device_id {
         // Treat empty string like `None` (system default), consistent with `device_exists`
         Some("") => {
             return host
                 .default_input_device()
+                .map(|device| (device, false))
                 .ok_or_else(|| Error::Audio("no input device available".to_string()));
         }
-        Some(name) => {
-            if let Ok(devices) = host.input_devices() {
-                for device in devices {
-                    let device_name_str = get_device_name(&device);
-                    if device_name_str == name {
-                        eprintln!("[Using audio device: {name}]");
-                        return Ok(device);
-                    }
-                }
+        Some(id) => {
+            if let Some(device) = lookup_device(&host, id) {
+                eprintln!("[Using audio device: {} ({id})]", get_device_name(&device));
+                return Ok((device, false));
             }
-            eprintln!("[Device '{name}' not found, falling back to default]");
+            eprintln!("[Device '{id}' not found, falling back to default]");
+            is_fallback = true;
         }
         None => {
             // No specific device requested; fall through to default below.
@@ -77,243 +349,1135 @@ fn find_device_by_name(device_name: Option<&str>) -> Result<Device> {
     }
 
     host.default_input_device()
+        .map(|device| (device, is_fallback))
         .ok_or_else(|| Error::Audio("no input device available".to_string()))
 }
-const AUDIO_GAIN: f32 = 3.0; // Amplify audio by 3x
 
-pub struct AudioRecorder {
-    samples: Arc<Mutex<Vec<f32>>>,
-    recording: Arc<AtomicBool>,
-    stream: Option<Stream>,
-    sample_rate: u32,
-    channels: usize,
-    // Store device and config for recreating stream after unmute
-    device: Device,
-    stream_config: StreamConfig,
-    sample_format: SampleFormat,
-    // Audio level for visualization (RMS of samples, typically 0.0 to 1.0, may exceed for loud audio)
-    audio_level: Arc<AtomicU32>,
+/// Capture format requested in settings; unset fields keep the device default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CaptureFormat {
+    /// 16kHz captures in Whisper's rate and skips resampling
+    pub sample_rate: Option<u32>,
+    pub sample_format: Option<SampleFormat>,
+    /// Frames per callback; smaller buffers lower latency
+    pub buffer_size: Option<u32>,
 }
 
-impl AudioRecorder {
-    pub fn new(device_name: Option<&str>) -> Result<Self> {
-        let device = find_device_by_name(device_name)?;
+/// Choose the input config closest to `format` among those the device supports.
+/// Anything the device can't do falls back to its default config.
+pub fn select_input_config(
+    device: &Device,
+    format: CaptureFormat,
+) -> Result<(StreamConfig, SampleFormat)> {
+    let default = device
+        .default_input_config()
+        .map_err(|e| Error::Audio(format!("failed to get default input config: {e}")))?;
 
-        eprintln!("[Audio device: {}]", get_device_name(&device));
+    let supported = if format.sample_rate.is_none() && format.sample_format.is_none() {
+        default
+    } else {
+        find_supported_config(device, format, &default).unwrap_or_else(|| {
+            eprintln!("[Capture format {format:?} not supported by the device, using its default]");
+            default
+        })
+    };
+
+    let mut config: StreamConfig = supported.config();
+    if let Some(frames) = format.buffer_size {
+        let frames = match *supported.buffer_size() {
+            SupportedBufferSize::Range { min, max } => frames.clamp(min, max),
+            SupportedBufferSize::Unknown => frames,
+        };
+        config.buffer_size = BufferSize::Fixed(frames);
+    }
+    Ok((config, supported.sample_format()))
+}
 
-        let config = device
-            .default_input_config()
-            .map_err(|e| Error::Audio(format!("failed to get default input config: {e}")))?;
+/// Config for recording system audio from `device`: a monitor input's own config,
+/// or an output device's config, which cpal records in loopback.
+pub fn select_loopback_config(device: &Device) -> Result<(StreamConfig, SampleFormat)> {
+    let supported = device
+        .default_input_config()
+        .or_else(|_| device.default_output_config())
+        .map_err(|e| Error::Audio(format!("failed to get loopback config: {e}")))?;
+    Ok((supported.config(), supported.sample_format()))
+}
 
-        eprintln!(
-            "[Audio config: sample_rate={}, channels={}, format={:?}]",
-            config.sample_rate(),
-            config.channels(),
-            config.sample_format()
-        );
+/// What a recording hotkey captures
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CaptureMode {
+    #[default]
+    Microphone,
+    /// System audio from the loopback device
+    Loopback,
+    /// Microphone and system audio mixed into one recording
+    Mix,
+}
 
-        let sample_rate = config.sample_rate();
-        let channels = config.channels() as usize;
-        let sample_format = config.sample_format();
-        let stream_config: StreamConfig = config.clone().into();
-        let samples: Arc<Mutex<Vec<f32>>> = Arc::new(Mutex::new(Vec::new()));
-        let recording = Arc::new(AtomicBool::new(false));
-        let audio_level = Arc::new(AtomicU32::new(0));
-
-        let stream = Self::create_stream(
-            &device,
-            &stream_config,
-            sample_format,
-            samples.clone(),
-            recording.clone(),
-            audio_level.clone(),
-        )?;
+impl CaptureMode {
+    pub fn uses_microphone(self) -> bool {
+        matches!(self, Self::Microphone | Self::Mix)
+    }
+
+    pub fn uses_loopback(self) -> bool {
+        matches!(self, Self::Loopback | Self::Mix)
+    }
+}
+
+/// Default and maximum gain of each source in a mix
+pub const DEFAULT_MIX_GAIN: f32 = 1.0;
+pub const MAX_MIX_GAIN: f32 = 4.0;
+
+/// Where system audio is recorded from, and how it is mixed with the microphone
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoopbackSettings {
+    /// Loopback device id, or None to pick one automatically
+    pub device: Option<String>,
+    /// Gain of the microphone in mix mode, applied after the input gain
+    pub mic_gain: f32,
+    /// Gain of the system audio in loopback and mix mode
+    pub loopback_gain: f32,
+}
 
-        // Start the stream immediately and keep it running
-        stream
-            .play()
-            .map_err(|e| Error::Audio(format!("failed to start stream: {e}")))?;
+impl Default for LoopbackSettings {
+    fn default() -> Self {
+        Self {
+            device: None,
+            mic_gain: DEFAULT_MIX_GAIN,
+            loopback_gain: DEFAULT_MIX_GAIN,
+        }
+    }
+}
+
+/// A supported config with the requested rate and format, preferring the
+/// default channel count so the channel selection keeps its meaning.
+fn find_supported_config(
+    device: &Device,
+    format: CaptureFormat,
+    default: &SupportedStreamConfig,
+) -> Option<SupportedStreamConfig> {
+    let sample_rate = format.sample_rate.unwrap_or(default.sample_rate());
+    let sample_format = format.sample_format.unwrap_or(default.sample_format());
+    let default_channels = default.channels();
+
+    device
+        .supported_input_configs()
+        .ok()?
+        .filter(|range| range.sample_format() == sample_format)
+        .filter_map(|range| range.try_with_sample_rate(sample_rate))
+        .max_by_key(|config| config.channels() == default_channels)
+}
+
+/// Samples at or above this magnitude are counted as clipped
+pub const CLIP_THRESHOLD: f32 = 0.99;
+/// Auto gain normalises the recording peak to this level
+const AGC_TARGET_PEAK: f32 = 0.9;
+/// Bounds for the automatically computed gain
+const AGC_MIN_GAIN: f32 = 0.1;
+const AGC_MAX_GAIN: f32 = 10.0;
+/// Bounds for a manual gain override
+const MANUAL_GAIN_MIN: f32 = 0.1;
+const MANUAL_GAIN_MAX: f32 = 20.0;
+/// Gain applied to the visualized level in auto mode (matches the previous fixed gain)
+const AUTO_LEVEL_GAIN: f32 = 3.0;
+/// Default and maximum length of the pre-roll buffer kept while idle
+pub const DEFAULT_PREROLL_MS: u32 = 500;
+pub const MAX_PREROLL_MS: u32 = 1000;
+/// Default and maximum time the recording keeps running after the hotkey is released
+pub const DEFAULT_TRAILING_MS: u32 = 150;
+pub const MAX_TRAILING_MS: u32 = 1000;
+/// Default and maximum configurable recording length
+pub const DEFAULT_MAX_RECORDING_SECS: u32 = 300;
+pub const MAX_RECORDING_SECS_LIMIT: u32 = 1800;
+/// Hard ceiling on the 16kHz recording buffer regardless of settings (~115 MB)
+const MAX_RECORDING_SAMPLES: usize =
+    MAX_RECORDING_SECS_LIMIT as usize * WHISPER_SAMPLE_RATE as usize;
+
+/// Which input channels of a multichannel device make up the mono recording
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ChannelSelection {
+    /// Average every channel
+    #[default]
+    Average,
+    /// Use a single channel (0-based)
+    Single(usize),
+    /// Average a subset of channels (0-based)
+    Subset(Vec<usize>),
+}
+
+impl ChannelSelection {
+    /// Channel indices to mix for a device with `channels` channels.
+    /// Indices the device doesn't have are ignored; if none remain, all channels are used.
+    fn indices(&self, channels: usize) -> Vec<usize> {
+        let selected: Vec<usize> = match self {
+            Self::Average => Vec::new(),
+            Self::Single(channel) => vec![*channel],
+            Self::Subset(subset) => subset.clone(),
+        };
+        let mut valid: Vec<usize> = selected.into_iter().filter(|&c| c < channels).collect();
+        valid.sort_unstable();
+        valid.dedup();
+        if valid.is_empty() {
+            (0..channels).collect()
+        } else {
+            valid
+        }
+    }
+}
+
+/// Seconds of audio the ring buffer between the callback and the drain worker can hold
+const RING_BUFFER_SECS: usize = 2;
+/// How often the drain worker moves samples out of the ring buffer
+const DRAIN_INTERVAL: Duration = Duration::from_millis(10);
+/// Input frames per resampler chunk
+const RESAMPLE_CHUNK_SIZE: usize = 1024;
+/// Cutoff of the rumble high-pass filter
+const HIGH_PASS_CUTOFF_HZ: f32 = 80.0;
+/// Noise gate opens above this frame RMS (about -46 dBFS)
+const GATE_THRESHOLD_RMS: f32 = 0.005;
+/// Gain applied while the gate is closed (-20 dB)
+const GATE_FLOOR_GAIN: f32 = 0.1;
+/// Noise gate analysis frame (10ms at 16kHz)
+const GATE_FRAME_LEN: usize = WHISPER_SAMPLE_RATE as usize / 100;
+/// Frames the gate stays open after the signal drops (~200ms)
+const GATE_HOLD_FRAMES: usize = 20;
+/// Denoise FFT frame (32ms at 16kHz)
+const DENOISE_FRAME_LEN: usize = 512;
+/// The noise profile averages the quietest 1/N of frames
+const DENOISE_NOISE_FRACTION: usize = 10;
+/// How aggressively the noise profile is subtracted
+const DENOISE_OVERSUBTRACTION: f32 = 2.0;
+/// Minimum gain per frequency bin, limits musical noise
+const DENOISE_FLOOR_GAIN: f32 = 0.1;
+
+/// How recorded audio is amplified before transcription
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GainMode {
+    /// Peak-normalise each recording
+    Auto,
+    /// Apply a fixed gain factor
+    Manual(f32),
+}
+
+impl GainMode {
+    /// Create a manual gain, clamped to the supported range.
+    pub fn manual(gain: f32) -> Self {
+        Self::Manual(gain.clamp(MANUAL_GAIN_MIN, MANUAL_GAIN_MAX))
+    }
+
+    /// Gain used to scale the visualized audio level.
+    fn level_gain(self) -> f32 {
+        match self {
+            Self::Auto => AUTO_LEVEL_GAIN,
+            Self::Manual(gain) => gain,
+        }
+    }
+}
+
+/// Resamples mono audio in fixed-size chunks as it arrives, so the output is
+/// ready as soon as the recording ends.
+struct ChunkResampler {
+    resampler: Fft<f32>,
+    from_rate: u32,
+    to_rate: u32,
+    // Input frames waiting for a full chunk
+    pending: Vec<f32>,
+    input_chunk: Vec<Vec<f32>>,
+    output_chunk: Vec<Vec<f32>>,
+    output: Vec<f32>,
+    // Leading output frames that are resampler delay rather than signal
+    delay_remaining: usize,
+    input_frames: usize,
+}
+
+impl ChunkResampler {
+    fn new(from_rate: u32, to_rate: u32) -> Result<Self> {
+        let resampler = Fft::<f32>::new(
+            from_rate as usize,
+            to_rate as usize,
+            RESAMPLE_CHUNK_SIZE,
+            1, // sub_chunks
+            1, // channels (mono)
+            FixedSync::Input,
+        )
+        .map_err(|e| Error::Resample(format!("failed to create resampler: {e}")))?;
+
+        let output_frames_max = resampler.output_frames_max();
+        let delay_remaining = resampler.output_delay();
 
         Ok(Self {
-            samples,
-            recording,
-            stream: Some(stream),
+            resampler,
+            from_rate,
+            to_rate,
+            pending: Vec::with_capacity(RESAMPLE_CHUNK_SIZE),
+            input_chunk: vec![vec![0.0; RESAMPLE_CHUNK_SIZE]],
+            output_chunk: vec![vec![0.0; output_frames_max]],
+            output: Vec::new(),
+            delay_remaining,
+            input_frames: 0,
+        })
+    }
+
+    /// Queue mono samples, resampling every complete chunk.
+    fn push(&mut self, samples: &[f32]) -> Result<()> {
+        self.input_frames += samples.len();
+        self.pending.extend_from_slice(samples);
+
+        let mut offset = 0;
+        while self.pending.len() - offset >= RESAMPLE_CHUNK_SIZE {
+            self.input_chunk[0]
+                .copy_from_slice(&self.pending[offset..offset + RESAMPLE_CHUNK_SIZE]);
+            self.process_chunk()?;
+            offset += RESAMPLE_CHUNK_SIZE;
+        }
+        self.pending.drain(..offset);
+        Ok(())
+    }
+
+    /// Resample the current input chunk and append it to the output, skipping the delay.
+    fn process_chunk(&mut self) -> Result<()> {
+        use audioadapter_buffers::direct::SequentialSliceOfVecs;
+
+        let input_adapter =
+            SequentialSliceOfVecs::new(&self.input_chunk, 1, RESAMPLE_CHUNK_SIZE)
+                .map_err(|e| Error::Resample(format!("failed to create input adapter: {e}")))?;
+        let output_len = self.output_chunk[0].len();
+        let mut output_adapter =
+            SequentialSliceOfVecs::new_mut(&mut self.output_chunk, 1, output_len)
+                .map_err(|e| Error::Resample(format!("failed to create output adapter: {e}")))?;
+
+        let (_, frames_written) = self
+            .resampler
+            .process_into_buffer(&input_adapter, &mut output_adapter, None)
+            .map_err(|e| Error::Resample(format!("failed to resample: {e}")))?;
+
+        let skip = self.delay_remaining.min(frames_written);
+        self.delay_remaining -= skip;
+        self.output
+            .extend_from_slice(&self.output_chunk[0][skip..frames_written]);
+        Ok(())
+    }
+
+    /// Flush the remaining input and return the resampled audio.
+    /// The resampler is reset and can be reused for the next recording.
+    fn finish(&mut self) -> Result<Vec<f32>> {
+        let expected_len = usize::try_from(
+            (self.input_frames as u64 * u64::from(self.to_rate))
+                .div_ceil(u64::from(self.from_rate)),
+        )
+        .unwrap_or(usize::MAX);
+
+        // Pad the tail with silence until the delayed signal has been flushed out
+        while self.output.len() < expected_len {
+            let remaining = self.pending.len();
+            self.input_chunk[0].fill(0.0);
+            self.input_chunk[0][..remaining].copy_from_slice(&self.pending);
+            self.pending.clear();
+            self.process_chunk()?;
+        }
+
+        let mut output = std::mem::take(&mut self.output);
+        output.truncate(expected_len);
+        self.reset();
+        Ok(output)
+    }
+
+    fn reset(&mut self) {
+        self.resampler.reset();
+        self.pending.clear();
+        self.output.clear();
+        self.delay_remaining = self.resampler.output_delay();
+        self.input_frames = 0;
+    }
+}
+
+/// Downmixes and resamples interleaved device audio to 16kHz mono while recording.
+struct CapturePipeline {
+    sample_rate: u32,
+    channels: usize,
+    // Channels mixed into the mono signal
+    selected: Vec<usize>,
+    // None when the device already captures at 16kHz
+    resampler: Option<ChunkResampler>,
+    output: Vec<f32>,
+    // Frames accepted so far and the most this recording may hold, at the input rate
+    frames: usize,
+    max_frames: usize,
+    // First error hit while processing, reported when the recording is finished
+    error: Option<Error>,
+    // Where the recording is spilled as it grows, and how much of the output it holds
+    spill: Option<SpillFile>,
+    spilled: usize,
+}
+
+impl CapturePipeline {
+    fn new(sample_rate: u32, channels: usize, selection: &ChannelSelection) -> Result<Self> {
+        let resampler = if sample_rate == WHISPER_SAMPLE_RATE {
+            None
+        } else {
+            Some(ChunkResampler::new(sample_rate, WHISPER_SAMPLE_RATE)?)
+        };
+
+        let mut pipeline = Self {
             sample_rate,
             channels,
-            device,
-            stream_config,
-            sample_format,
-            audio_level,
-        })
+            selected: selection.indices(channels),
+            resampler,
+            output: Vec::new(),
+            frames: 0,
+            max_frames: 0,
+            error: None,
+            spill: None,
+            spilled: 0,
+        };
+        pipeline.set_max_samples(MAX_RECORDING_SAMPLES);
+        Ok(pipeline)
     }
 
-    fn create_stream(
-        device: &Device,
-        config: &StreamConfig,
-        sample_format: SampleFormat,
-        samples: Arc<Mutex<Vec<f32>>>,
-        recording: Arc<AtomicBool>,
-        audio_level: Arc<AtomicU32>,
-    ) -> Result<Stream> {
-        let stream = match sample_format {
-            SampleFormat::F32 => {
-                build_input_stream::<f32>(device, config, samples, recording, audio_level)
-            }
-            SampleFormat::I16 => {
-                build_input_stream::<i16>(device, config, samples, recording, audio_level)
-            }
-            SampleFormat::I32 => {
-                build_input_stream::<i32>(device, config, samples, recording, audio_level)
+    /// Limit the recording to `samples` at 16kHz; audio beyond that is dropped.
+    fn set_max_samples(&mut self, samples: usize) {
+        let samples = samples.min(MAX_RECORDING_SAMPLES) as u64;
+        let frames = samples * u64::from(self.sample_rate) / u64::from(WHISPER_SAMPLE_RATE);
+        self.max_frames = usize::try_from(frames).unwrap_or(usize::MAX);
+    }
+
+    /// Whether the recording has reached its length limit.
+    fn is_full(&self) -> bool {
+        self.frames >= self.max_frames
+    }
+
+    /// Process interleaved samples made up of whole frames.
+    fn push(&mut self, interleaved: &[f32]) {
+        if interleaved.is_empty() || self.error.is_some() || self.is_full() {
+            return;
+        }
+
+        // Mix the selected channels down to mono if needed
+        let mut mono = if self.channels > 1 {
+            downmix(interleaved, self.channels, &self.selected)
+        } else {
+            interleaved.to_vec()
+        };
+        mono.truncate(self.max_frames - self.frames);
+        self.frames += mono.len();
+
+        match self.resampler.as_mut() {
+            Some(resampler) => {
+                if let Err(e) = resampler.push(&mono) {
+                    self.error = Some(e);
+                }
             }
-            format => Err(Error::Audio(format!(
-                "unsupported sample format: {format:?}"
-            ))),
-        }?;
+            None => self.output.extend_from_slice(&mono),
+        }
+        self.spill_output();
+    }
+
+    /// Append the output produced since the last call to the spill file.
+    fn spill_output(&mut self) {
+        let Some(spill) = self.spill.as_mut() else {
+            return;
+        };
+        let output = self.resampler.as_ref().map_or(&self.output, |r| &r.output);
+        if let Err(e) = spill.append(&output[self.spilled..]) {
+            // Keep recording in memory; only the crash safety is lost
+            eprintln!("[{e}]");
+            self.spill = None;
+        }
+        self.spilled = output.len();
+    }
+
+    /// Return the 16kHz mono recording and reset for the next one.
+    fn finish(&mut self) -> Result<Vec<f32>> {
+        if let Some(e) = self.error.take() {
+            self.clear();
+            return Err(e);
+        }
+
+        self.frames = 0;
+        self.spill = None;
+        self.spilled = 0;
+        match self.resampler.as_mut() {
+            Some(resampler) => resampler.finish(),
+            None => Ok(std::mem::take(&mut self.output)),
+        }
+    }
+
+    /// Discard any audio processed so far.
+    fn clear(&mut self) {
+        self.output.clear();
+        self.frames = 0;
+        self.error = None;
+        self.spill = None;
+        self.spilled = 0;
+        if let Some(resampler) = self.resampler.as_mut() {
+            resampler.reset();
+        }
+    }
+}
+
+/// Sample buffers filled from the ring buffer by the drain worker.
+/// The realtime callback never touches this lock.
+struct CaptureBuffers {
+    // Read side of the ring buffer written by the current stream's callback
+    consumer: Option<Consumer<f32>>,
+    // The recording in progress, already converted to 16kHz mono
+    pipeline: CapturePipeline,
+    // Rolling window of the most recent interleaved samples captured while idle
+    preroll: VecDeque<f32>,
+    preroll_capacity: usize,
+    // The most recent mono samples at the device rate, for metering
+    meter_window: VecDeque<f32>,
+}
+
+impl CaptureBuffers {
+    /// Move everything the callback has produced so far into the recording,
+    /// or into the pre-roll while idle.
+    fn drain(&mut self, recording: bool) {
+        let Some(consumer) = self.consumer.as_mut() else {
+            return;
+        };
+        // Only read whole frames so the downmix stays aligned
+        let channels = self.pipeline.channels;
+        let available = consumer.slots() / channels * channels;
+        let Ok(chunk) = consumer.read_chunk(available) else {
+            return;
+        };
+
+        let (first, second) = chunk.as_slices();
+        let interleaved = if second.is_empty() {
+            Cow::Borrowed(first)
+        } else {
+            // The ring buffer wrapped, so a frame may straddle both slices
+            Cow::Owned([first, second].concat())
+        };
+
+        let mono = downmix(&interleaved, channels, &self.pipeline.selected);
+        push_preroll(&mut self.meter_window, METER_WINDOW_LEN, mono.iter());
+
+        if recording {
+            self.pipeline.push(&interleaved);
+        } else if self.preroll_capacity > 0 {
+            push_preroll(&mut self.preroll, self.preroll_capacity, interleaved.iter());
+        }
+        chunk.commit_all();
+    }
+}
+
+/// Append samples to the pre-roll, discarding the oldest beyond `capacity`.
+fn push_preroll<'a>(
+    preroll: &mut VecDeque<f32>,
+    capacity: usize,
+    samples: impl Iterator<Item = &'a f32>,
+) {
+    preroll.extend(samples);
+    let excess = preroll.len().saturating_sub(capacity);
+    preroll.drain(..excess);
+}
+
+/// Number of interleaved samples needed to hold `preroll_ms` of audio.
+fn preroll_capacity(preroll_ms: u32, sample_rate: u32, channels: usize) -> usize {
+    let frames = u64::from(preroll_ms.min(MAX_PREROLL_MS)) * u64::from(sample_rate) / 1000;
+    usize::try_from(frames).unwrap_or(0) * channels
+}
+
+/// State shared between the recorder, the drain worker and the realtime audio callback
+struct CaptureShared {
+    buffers: Mutex<CaptureBuffers>,
+    recording: AtomicBool,
+    // Set by the stream's error callback when the device is gone or the stream is invalid
+    stream_failed: AtomicBool,
+    // Set by the drain worker once the recording hits its length limit
+    limit_reached: AtomicBool,
+    // Samples dropped by the callback because the ring buffer was full
+    overruns: AtomicUsize,
+    // Gain applied to the visualized audio level
+    level_gain: AtomicU32,
+}
 
-        Ok(stream)
+impl CaptureShared {
+    fn new(
+        sample_rate: u32,
+        channels: usize,
+        channel_selection: &ChannelSelection,
+        preroll_ms: u32,
+        level_gain: f32,
+    ) -> Result<Arc<Self>> {
+        Ok(Arc::new(Self {
+            buffers: Mutex::new(CaptureBuffers {
+                consumer: None,
+                pipeline: CapturePipeline::new(sample_rate, channels, channel_selection)?,
+                preroll: VecDeque::new(),
+                preroll_capacity: preroll_capacity(preroll_ms, sample_rate, channels),
+                meter_window: VecDeque::with_capacity(METER_WINDOW_LEN),
+            }),
+            recording: AtomicBool::new(false),
+            stream_failed: AtomicBool::new(false),
+            limit_reached: AtomicBool::new(false),
+            overruns: AtomicUsize::new(0),
+            level_gain: AtomicU32::new(level_gain.to_bits()),
+        }))
     }
 
-    pub fn start(&self) {
-        // Clear any previous samples and start recording
-        self.samples.lock().unwrap().clear();
+    /// Start a new recording of the `selected` input channels with the pre-roll so the
+    /// first syllable isn't lost, spilling it to `spill` as it grows.
+    /// Returns the number of pre-roll frames it starts with.
+    fn begin_recording(
+        &self,
+        selected: Vec<usize>,
+        max_samples: usize,
+        spill: Option<SpillFile>,
+    ) -> usize {
+        // Holding the lock while flipping the flag keeps the worker from splitting a chunk
+        let mut guard = self.buffers.lock().unwrap();
+        let buffers = &mut *guard;
+        buffers.drain(false);
+        let preroll_frames = buffers.preroll.len() / buffers.pipeline.channels;
+        buffers.pipeline.clear();
+        buffers.pipeline.selected = selected;
+        buffers.pipeline.set_max_samples(max_samples);
+        buffers.pipeline.spill = spill;
+        self.limit_reached.store(false, Ordering::SeqCst);
+        let (first, second) = buffers.preroll.as_slices();
+        let preroll = [first, second].concat();
+        buffers.pipeline.push(&preroll);
+        buffers.preroll.clear();
         self.recording.store(true, Ordering::SeqCst);
-        eprintln!("[Recording started]");
+        preroll_frames
     }
 
-    pub fn stop(&self) -> Result<Vec<f32>> {
-        // Keep recording flag on for a moment to capture trailing audio
-        std::thread::sleep(std::time::Duration::from_millis(150));
+    /// End the recording and take its 16kHz mono samples, leaving an empty buffer
+    /// (clears sensitive audio data).
+    fn finish_recording(&self) -> Result<Vec<f32>> {
+        // Anything still in the ring buffer belongs to this recording, and the pre-roll is
+        // cleared so the next recording doesn't repeat this one's tail.
+        // Audio was downmixed and resampled while recording, so only the tail is left to flush.
+        let mut buffers = self.buffers.lock().unwrap();
+        buffers.drain(true);
         self.recording.store(false, Ordering::SeqCst);
+        buffers.preroll.clear();
+        buffers.meter_window.clear();
+        buffers.pipeline.finish()
+    }
+
+    /// Peak, RMS and spectrum of the most recent input at `sample_rate`.
+    fn metering(&self, sample_rate: u32) -> Metering {
+        // Copy the window so the FFT runs without holding the drain worker's lock
+        let window: Vec<f32> = {
+            let buffers = self.buffers.lock().unwrap();
+            buffers.meter_window.iter().copied().collect()
+        };
+        let level_gain = f32::from_bits(self.level_gain.load(Ordering::Relaxed));
+        Metering::analyze(&window, sample_rate, level_gain)
+    }
 
-        // Reset audio level
-        self.audio_level.store(0, Ordering::Relaxed);
+    fn drain(&self) {
+        let mut buffers = self.buffers.lock().unwrap();
+        let recording = self.recording.load(Ordering::SeqCst);
+        buffers.drain(recording);
+        if recording && buffers.pipeline.is_full() {
+            self.limit_reached.store(true, Ordering::SeqCst);
+        }
+    }
+}
 
-        // Take ownership of samples, leaving an empty buffer (clears sensitive audio data)
-        let raw_samples = std::mem::take(&mut *self.samples.lock().unwrap());
-        eprintln!("[Raw samples collected: {}]", raw_samples.len());
+/// Where an audio source delivers interleaved samples, from its realtime thread
+pub struct SampleSink {
+    producer: Producer<f32>,
+    channels: usize,
+    shared: Arc<CaptureShared>,
+}
 
-        if raw_samples.is_empty() {
-            return Ok(Vec::new());
+impl SampleSink {
+    /// Queue samples for the drain worker without blocking.
+    /// Only whole frames are written so the reader stays channel-aligned.
+    pub fn push<T>(&mut self, data: &[T])
+    where
+        T: Copy,
+        f32: FromSample<T>,
+    {
+        let writable = data.len().min(self.producer.slots()) / self.channels * self.channels;
+        if let Ok(chunk) = self.producer.write_chunk_uninit(writable) {
+            chunk.fill_from_iter(data.iter().map(|&sample| f32::from_sample(sample)));
         }
+        if writable < data.len() {
+            self.shared
+                .overruns
+                .fetch_add(data.len() - writable, Ordering::Relaxed);
+        }
+    }
+
+    /// A handle for the source's error callback to report an unrecoverable stream.
+    pub fn failure(&self) -> StreamFailure {
+        StreamFailure(self.shared.clone())
+    }
+}
+
+/// Flags the capture stream as failed, e.g. because its device went away
+pub struct StreamFailure(Arc<CaptureShared>);
+
+impl StreamFailure {
+    pub fn report(&self) {
+        self.0.stream_failed.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Start `source` delivering into a fresh ring buffer read by the drain worker.
+fn start_source(source: &mut dyn AudioSource, shared: &Arc<CaptureShared>) -> Result<()> {
+    let channels = source.channels();
+    let capacity = source.sample_rate() as usize * channels * RING_BUFFER_SECS;
+    let (producer, consumer) = RingBuffer::new(capacity);
+
+    // Replace the previous source's consumer (its producer was dropped when it stopped)
+    shared.buffers.lock().unwrap().consumer = Some(consumer);
+    source.start(SampleSink {
+        producer,
+        channels,
+        shared: shared.clone(),
+    })
+}
+
+/// System audio captured for the length of one recording
+struct LoopbackCapture {
+    shared: Arc<CaptureShared>,
+    source: Box<dyn AudioSource>,
+    // 16kHz samples of microphone pre-roll recorded before the loopback started
+    offset: usize,
+}
+
+impl LoopbackCapture {
+    fn start(mut source: Box<dyn AudioSource>, max_samples: usize) -> Result<Self> {
+        let shared = CaptureShared::new(
+            source.sample_rate(),
+            source.channels(),
+            &ChannelSelection::Average,
+            0,
+            AUTO_LEVEL_GAIN,
+        )?;
+        start_source(source.as_mut(), &shared)?;
+        spawn_drain_worker(Arc::downgrade(&shared));
+        let selected = ChannelSelection::Average.indices(source.channels());
+        shared.begin_recording(selected, max_samples, None);
+        Ok(Self {
+            shared,
+            source,
+            offset: 0,
+        })
+    }
+
+    /// Spill the recording to `spill`, led by the offset's silence so it lines up with
+    /// the microphone's spill. Audio recorded so far is written on the next chunk.
+    fn spill_to(&self, mut spill: SpillFile) {
+        if let Err(e) = spill.append(&vec![0.0; self.offset]) {
+            eprintln!("[{e}]");
+            return;
+        }
+        self.shared.buffers.lock().unwrap().pipeline.spill = Some(spill);
+    }
+
+    /// Stop capturing and take the 16kHz mono recording and its offset.
+    fn finish(mut self) -> Result<(Vec<f32>, usize)> {
+        let output = self.shared.finish_recording();
+        self.source.stop();
+        Ok((output?, self.offset))
+    }
+}
+
+/// Spawn the worker that drains the ring buffer until the recorder is dropped.
+fn spawn_drain_worker(shared: Weak<CaptureShared>) {
+    thread::spawn(move || {
+        while let Some(shared) = shared.upgrade() {
+            shared.drain();
+
+            let overruns = shared.overruns.swap(0, Ordering::Relaxed);
+            if overruns > 0 {
+                eprintln!("[Audio ring buffer overrun: {overruns} samples dropped]");
+            }
+
+            drop(shared);
+            thread::sleep(DRAIN_INTERVAL);
+        }
+    });
+}
+
+pub struct AudioRecorder {
+    shared: Arc<CaptureShared>,
+    // Kept while muted so capture can resume on unmute
+    source: Box<dyn AudioSource>,
+    capture_format: CaptureFormat,
+    gain_mode: GainMode,
+    preroll_ms: u32,
+    channel_selection: ChannelSelection,
+    dsp: DspSettings,
+    max_recording_secs: u32,
+    trailing_ms: u32,
+    loopback: LoopbackSettings,
+    // What the current or last recording captures
+    mode: CaptureMode,
+    // System audio recorded alongside the microphone, in loopback and mix mode
+    loopback_capture: Option<LoopbackCapture>,
+    muted: bool,
+    // Device requested in settings, restored automatically when it reappears
+    preferred_device: Option<String>,
+    // True while the default device stands in for a missing preferred device
+    using_fallback: bool,
+}
+
+impl AudioRecorder {
+    /// Record from an input device, or the system default if `device_id` is None or missing.
+    pub fn new(
+        device_id: Option<&str>,
+        capture_format: CaptureFormat,
+        gain_mode: GainMode,
+        preroll_ms: u32,
+        channel_selection: ChannelSelection,
+        dsp: DspSettings,
+        max_recording_secs: u32,
+    ) -> Result<Self> {
+        let (device, using_fallback) = find_device(device_id)?;
 
-        // Convert stereo to mono if needed
-        let mono = if self.channels > 1 {
-            stereo_to_mono(&raw_samples, self.channels)
+        eprintln!("[Audio device: {}]", get_device_name(&device));
+
+        let source = CpalSource::new(device, capture_format)?;
+        let mut recorder = Self::with_source(
+            Box::new(source),
+            gain_mode,
+            preroll_ms,
+            channel_selection,
+            dsp,
+            max_recording_secs,
+        )?;
+        recorder.capture_format = capture_format;
+        recorder.preferred_device = device_id.filter(|id| !id.is_empty()).map(String::from);
+        recorder.using_fallback = using_fallback;
+        Ok(recorder)
+    }
+
+    /// Record from any audio source, e.g. a test signal when there is no sound card.
+    pub fn with_source(
+        mut source: Box<dyn AudioSource>,
+        gain_mode: GainMode,
+        preroll_ms: u32,
+        channel_selection: ChannelSelection,
+        dsp: DspSettings,
+        max_recording_secs: u32,
+    ) -> Result<Self> {
+        let shared = CaptureShared::new(
+            source.sample_rate(),
+            source.channels(),
+            &channel_selection,
+            preroll_ms,
+            gain_mode.level_gain(),
+        )?;
+
+        // Start capturing immediately and keep it running
+        start_source(source.as_mut(), &shared)?;
+        spawn_drain_worker(Arc::downgrade(&shared));
+
+        Ok(Self {
+            shared,
+            source,
+            capture_format: CaptureFormat::default(),
+            gain_mode,
+            preroll_ms,
+            channel_selection,
+            dsp,
+            max_recording_secs,
+            trailing_ms: DEFAULT_TRAILING_MS,
+            loopback: LoopbackSettings::default(),
+            mode: CaptureMode::default(),
+            loopback_capture: None,
+            muted: false,
+            preferred_device: None,
+            using_fallback: false,
+        })
+    }
+
+    /// Start a recording. Loopback and mix modes open the loopback device for its length.
+    pub fn start(&mut self, mode: CaptureMode, spill: Option<SpillFile>) -> Result<()> {
+        let loopback: Option<Box<dyn AudioSource>> = if mode.uses_loopback() {
+            let device = find_loopback_device(self.loopback.device.as_deref())?;
+            Some(Box::new(CpalSource::loopback(device)?))
         } else {
-            raw_samples
+            None
         };
+        self.start_with_loopback(mode, loopback, spill)
+    }
 
-        // Resample to 16kHz if needed
-        if self.sample_rate == WHISPER_SAMPLE_RATE {
-            Ok(mono)
-        } else {
-            resample(&mono, self.sample_rate, WHISPER_SAMPLE_RATE)
+    /// Start a recording that captures system audio from `loopback` if the mode uses it.
+    /// The microphone is spilled to `spill` and the system audio to its companion file,
+    /// see `SpillFile::loopback()`, so `spill` stays empty in loopback mode. Recovery
+    /// relies on this layout.
+    pub fn start_with_loopback(
+        &mut self,
+        mode: CaptureMode,
+        loopback: Option<Box<dyn AudioSource>>,
+        spill: Option<SpillFile>,
+    ) -> Result<()> {
+        let max_samples = self.max_recording_secs as usize * WHISPER_SAMPLE_RATE as usize;
+        // System audio is spilled to a companion file and mixed in on recovery
+        let loopback_spill = match spill.as_ref() {
+            Some(spill) if mode.uses_loopback() => spill
+                .loopback()
+                .map_err(|e| eprintln!("[Recording system audio without a spill file: {e}]"))
+                .ok(),
+            _ => None,
+        };
+        let mut loopback_capture = match loopback {
+            Some(source) if mode.uses_loopback() => {
+                eprintln!("[Recording system audio from {}]", source.name());
+                Some(LoopbackCapture::start(source, max_samples)?)
+            }
+            None if mode.uses_loopback() => {
+                return Err(Error::Audio(
+                    "no loopback source to record from".to_string(),
+                ));
+            }
+            _ => None,
+        };
+
+        if mode.uses_microphone() {
+            let selected = self.channel_selection.indices(self.source.channels());
+            let preroll_frames = self.shared.begin_recording(selected, max_samples, spill);
+            // The loopback starts at the key press, after the microphone's pre-roll
+            if let Some(capture) = loopback_capture.as_mut() {
+                capture.offset = preroll_frames * WHISPER_SAMPLE_RATE as usize
+                    / self.source.sample_rate() as usize;
+            }
+            eprintln!("[Recording started with {preroll_frames} pre-roll frames]");
+        }
+        if let (Some(capture), Some(spill)) = (loopback_capture.as_ref(), loopback_spill) {
+            capture.spill_to(spill);
+        }
+
+        self.mode = mode;
+        self.loopback_capture = loopback_capture;
+        Ok(())
+    }
+
+    pub fn stop(&mut self) -> Result<Vec<f32>> {
+        // Keep recording flag on for a moment to capture trailing audio
+        thread::sleep(Duration::from_millis(u64::from(self.trailing_ms)));
+
+        // Finish both captures before bailing out on an error, so neither keeps recording
+        let mic = self
+            .mode
+            .uses_microphone()
+            .then(|| self.shared.finish_recording());
+        let loopback = self.loopback_capture.take().map(LoopbackCapture::finish);
+
+        let mic = mic.transpose()?;
+        let loopback = loopback.transpose()?;
+        self.conditioning().apply(mic, loopback)
+    }
+
+    /// How recordings are conditioned and mixed with the current settings.
+    pub fn conditioning(&self) -> Conditioning {
+        Conditioning {
+            dsp: self.dsp,
+            gain_mode: self.gain_mode,
+            loopback: self.loopback.clone(),
         }
     }
 
+    /// End the recording right away and throw its audio away.
+    pub fn discard(&mut self) {
+        if self.mode.uses_microphone() {
+            let _ = self.shared.finish_recording();
+        }
+        if let Some(capture) = self.loopback_capture.take() {
+            let _ = capture.finish();
+        }
+        eprintln!("[Recording discarded]");
+    }
+
     /// Mute the microphone by stopping and dropping the audio stream.
     /// This releases the microphone so the system no longer shows it as in use.
     pub fn mute(&mut self) {
-        if self.stream.is_none() {
+        if self.muted {
             // Already muted
             return;
         }
 
-        // Drop the stream to release the microphone
-        self.stream = None;
-        self.recording.store(false, Ordering::SeqCst);
+        // Stop the source to release the microphone
+        self.source.stop();
+        self.muted = true;
+        self.shared.recording.store(false, Ordering::SeqCst);
         eprintln!("[Microphone muted]");
     }
 
     /// Unmute the microphone by recreating and starting the audio stream.
     pub fn unmute(&mut self) -> Result<()> {
-        if self.stream.is_some() {
+        if !self.muted {
             // Already unmuted
             return Ok(());
         }
 
-        let stream = Self::create_stream(
-            &self.device,
-            &self.stream_config,
-            self.sample_format,
-            self.samples.clone(),
-            self.recording.clone(),
-            self.audio_level.clone(),
-        )?;
-
-        stream
-            .play()
-            .map_err(|e| Error::Audio(format!("failed to start stream: {e}")))?;
+        // Discard pre-roll captured before muting
+        self.shared.buffers.lock().unwrap().preroll.clear();
 
-        self.stream = Some(stream);
+        start_source(self.source.as_mut(), &self.shared)?;
+        self.muted = false;
+        self.shared.stream_failed.store(false, Ordering::SeqCst);
         eprintln!("[Microphone unmuted]");
         Ok(())
     }
 
     /// Check if the microphone is currently muted.
     pub fn is_muted(&self) -> bool {
-        self.stream.is_none()
+        self.muted
+    }
+
+    /// Peak, RMS and spectrum of the most recent input, or of the system audio
+    /// while recording in loopback mode.
+    pub fn metering(&self) -> Metering {
+        match self.loopback_capture.as_ref() {
+            Some(capture) if !self.mode.uses_microphone() => {
+                capture.shared.metering(capture.source.sample_rate())
+            }
+            _ => self.shared.metering(self.source.sample_rate()),
+        }
+    }
+
+    /// Change how recordings are amplified. Takes effect from the next `stop()`.
+    pub fn set_gain_mode(&mut self, gain_mode: GainMode) {
+        self.gain_mode = gain_mode;
+        self.shared
+            .level_gain
+            .store(gain_mode.level_gain().to_bits(), Ordering::Relaxed);
     }
 
-    /// Get the current audio level (0.0 to 1.0).
-    pub fn get_audio_level(&self) -> f32 {
-        f32::from_bits(self.audio_level.load(Ordering::Relaxed))
+    /// Change how much audio is kept from before the hotkey is pressed.
+    pub fn set_preroll_ms(&mut self, preroll_ms: u32) {
+        self.preroll_ms = preroll_ms;
+        let mut buffers = self.shared.buffers.lock().unwrap();
+        buffers.preroll_capacity = preroll_capacity(
+            preroll_ms,
+            self.source.sample_rate(),
+            self.source.channels(),
+        );
+        buffers.preroll.clear();
+    }
+
+    /// Change which input channels are recorded. Takes effect from the next `start()`.
+    pub fn set_channel_selection(&mut self, selection: ChannelSelection) {
+        self.channel_selection = selection;
+    }
+
+    /// Change the maximum recording length. Takes effect from the next `start()`.
+    pub fn set_max_recording_secs(&mut self, secs: u32) {
+        self.max_recording_secs = secs;
+    }
+
+    /// Maximum length of a recording in seconds.
+    pub fn max_recording_secs(&self) -> u32 {
+        self.max_recording_secs
+    }
+
+    /// Change how long recording continues after the hotkey is released.
+    /// Takes effect from the next `stop()`.
+    pub fn set_trailing_ms(&mut self, trailing_ms: u32) {
+        self.trailing_ms = trailing_ms;
+    }
+
+    /// Whether the current recording hit its length limit and is no longer growing.
+    pub fn is_limit_reached(&self) -> bool {
+        self.shared.limit_reached.load(Ordering::SeqCst)
+            || self
+                .loopback_capture
+                .as_ref()
+                .is_some_and(|capture| capture.shared.limit_reached.load(Ordering::SeqCst))
+    }
+
+    /// Enable or disable preprocessing stages. Takes effect from the next `stop()`.
+    pub fn set_dsp(&mut self, dsp: DspSettings) {
+        self.dsp = dsp;
+    }
+
+    /// Change the loopback device and mix gains. Takes effect from the next `start()`.
+    pub fn set_loopback(&mut self, loopback: LoopbackSettings) {
+        self.loopback = loopback;
+    }
+
+    /// Change the requested capture format. Takes effect from the next `set_device()`.
+    pub fn set_capture_format(&mut self, format: CaptureFormat) {
+        self.capture_format = format;
     }
 
     /// Switch to a different audio input device.
-    /// If `device_name` is None or the device is not found, falls back to the default device.
-    pub fn set_device(&mut self, device_name: Option<&str>) -> Result<()> {
+    /// If `device_id` is None or the device is not found, falls back to the default device.
+    /// The requested device is remembered so it can be restored when it reappears.
+    pub fn set_device(&mut self, device_id: Option<&str>) -> Result<()> {
         // Find the new device
-        let device = find_device_by_name(device_name)?;
-        let device_name_str = get_device_name(&device);
-
-        // Get the new device's config
-        let config = device
-            .default_input_config()
-            .map_err(|e| Error::Audio(format!("failed to get default input config: {e}")))?;
-
-        eprintln!(
-            "[Switching to device: {} (sample_rate={}, channels={}, format={:?})]",
-            device_name_str,
-            config.sample_rate(),
-            config.channels(),
-            config.sample_format()
-        );
+        let (device, is_fallback) = find_device(device_id)?;
+        let source = CpalSource::new(device, self.capture_format)?;
+        self.switch_source(Box::new(source))?;
+
+        // Only once the switch succeeded, so the device monitor sees the device in use
+        self.preferred_device = device_id.filter(|id| !id.is_empty()).map(String::from);
+        self.using_fallback = is_fallback;
+        Ok(())
+    }
 
-        let sample_rate = config.sample_rate();
-        let channels = config.channels() as usize;
-        let sample_format = config.sample_format();
-        let stream_config: StreamConfig = config.into();
+    /// Switch to the system default device, e.g. after the current device disappeared.
+    /// The preferred device is kept so it can be restored later.
+    pub fn fall_back_to_default(&mut self) -> Result<()> {
+        let device = cpal::default_host()
+            .default_input_device()
+            .ok_or_else(|| Error::Audio("no input device available".to_string()))?;
 
-        // Stop current stream if running
-        let was_muted = self.stream.is_none();
-        self.stream = None;
-        self.recording.store(false, Ordering::SeqCst);
-        self.samples.lock().unwrap().clear();
+        let source = CpalSource::new(device, self.capture_format)?;
+        self.switch_source(Box::new(source))?;
+        self.using_fallback = self.preferred_device.is_some();
+        Ok(())
+    }
+
+    /// Device id requested in settings, or None for the system default.
+    pub fn preferred_device(&self) -> Option<&str> {
+        self.preferred_device.as_deref()
+    }
+
+    /// Whether the default device is in use because the preferred device is unavailable.
+    pub fn is_using_fallback(&self) -> bool {
+        self.using_fallback
+    }
+
+    /// Name of the device currently in use.
+    pub fn device_name(&self) -> String {
+        self.source.name()
+    }
+
+    /// Whether the stream reported that its device went away, or could not be recreated.
+    pub fn has_stream_failed(&self) -> bool {
+        self.shared.stream_failed.load(Ordering::SeqCst)
+            || (!self.muted && !self.source.is_running())
+    }
+
+    /// Record from a different source, keeping the mute state.
+    pub fn switch_source(&mut self, source: Box<dyn AudioSource>) -> Result<()> {
+        let device_name_str = source.name();
+        eprintln!("[Switching to device: {device_name_str}]");
 
-        // Update device info
-        self.device = device;
-        self.stream_config = stream_config;
-        self.sample_format = sample_format;
-        self.sample_rate = sample_rate;
-        self.channels = channels;
+        let sample_rate = source.sample_rate();
+        let channels = source.channels();
+        let pipeline = CapturePipeline::new(sample_rate, channels, &self.channel_selection)?;
 
-        // Recreate stream if we weren't muted
-        if !was_muted {
-            let stream = Self::create_stream(
-                &self.device,
-                &self.stream_config,
-                self.sample_format,
-                self.samples.clone(),
-                self.recording.clone(),
-                self.audio_level.clone(),
-            )?;
+        // Stop current source if running
+        self.source.stop();
+        self.shared.recording.store(false, Ordering::SeqCst);
+        {
+            let mut buffers = self.shared.buffers.lock().unwrap();
+            buffers.pipeline = pipeline;
+            buffers.preroll.clear();
+            buffers.meter_window.clear();
+            buffers.preroll_capacity = preroll_capacity(self.preroll_ms, sample_rate, channels);
+        }
 
-            stream
-                .play()
-                .map_err(|e| Error::Audio(format!("failed to start stream: {e}")))?;
+        self.source = source;
+        self.shared.stream_failed.store(false, Ordering::SeqCst);
 
-            self.stream = Some(stream);
+        // Start the new source if we weren't muted
+        if !self.muted {
+            start_source(self.source.as_mut(), &self.shared)?;
         }
 
         eprintln!("[Audio device switched to: {device_name_str}]");
@@ -321,58 +1485,355 @@ impl AudioRecorder {
     }
 }
 
-fn build_input_stream<T>(
-    device: &cpal::Device,
-    config: &cpal::StreamConfig,
-    samples: Arc<Mutex<Vec<f32>>>,
-    recording: Arc<AtomicBool>,
-    audio_level: Arc<AtomicU32>,
-) -> Result<Stream>
-where
-    T: cpal::Sample + cpal::SizedSample,
-    f32: FromSample<T>,
-{
-    let stream = device
-        .build_input_stream(
-            config,
-            move |data: &[T], _: &cpal::InputCallbackInfo| {
-                if recording.load(Ordering::SeqCst) {
-                    let mut buffer = samples.lock().unwrap();
-                    let mut sum_squares = 0.0f32;
-
-                    for &sample in data {
-                        // Apply gain and clamp to prevent clipping
-                        let amplified = (f32::from_sample(sample) * AUDIO_GAIN).clamp(-1.0, 1.0);
-                        buffer.push(amplified);
-                        sum_squares += amplified * amplified;
-                    }
-
-                    // Calculate RMS (root mean square) as audio level
-                    if !data.is_empty() {
-                        #[allow(clippy::cast_precision_loss)]
-                        let rms = (sum_squares / data.len() as f32).sqrt();
-                        audio_level.store(rms.to_bits(), Ordering::Relaxed);
-                    }
-                }
-            },
-            |err| eprintln!("audio stream error: {err}"),
-            None,
-        )
-        .map_err(|e| Error::Audio(format!("failed to build input stream: {e}")))?;
+/// Which preprocessing stages run on a recording before transcription
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DspSettings {
+    /// Remove rumble and DC offset below `HIGH_PASS_CUTOFF_HZ`
+    pub high_pass: bool,
+    /// Attenuate stretches quieter than `GATE_THRESHOLD_RMS`
+    pub noise_gate: bool,
+    /// Spectral subtraction of stationary background noise
+    pub denoise: bool,
+}
+
+/// A preprocessing stage that runs on the 16kHz mono recording in place
+trait Processor {
+    fn process(&mut self, samples: &mut [f32]) -> Result<()>;
+}
+
+/// Run the enabled preprocessing stages in order: high-pass, noise gate, denoise.
+fn preprocess(samples: &mut [f32], settings: DspSettings) -> Result<()> {
+    let mut chain: Vec<Box<dyn Processor>> = Vec::new();
+    if settings.high_pass {
+        chain.push(Box::new(HighPass::new(
+            HIGH_PASS_CUTOFF_HZ,
+            WHISPER_SAMPLE_RATE,
+        )));
+    }
+    if settings.noise_gate {
+        chain.push(Box::new(NoiseGate::default()));
+    }
+    if settings.denoise {
+        chain.push(Box::new(SpectralDenoise));
+    }
 
-    Ok(stream)
+    for stage in &mut chain {
+        stage.process(samples)?;
+    }
+    Ok(())
+}
+
+/// Second-order Butterworth high-pass filter (biquad)
+struct HighPass {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl HighPass {
+    fn new(cutoff_hz: f32, sample_rate: u32) -> Self {
+        #[allow(clippy::cast_precision_loss)]
+        let w0 = 2.0 * std::f32::consts::PI * cutoff_hz / sample_rate as f32;
+        let alpha = w0.sin() / (2.0 * std::f32::consts::FRAC_1_SQRT_2);
+        let cos_w0 = w0.cos();
+        let a0 = 1.0 + alpha;
+        let b0 = (1.0 + cos_w0) * 0.5 / a0;
+
+        Self {
+            b0,
+            b1: -2.0 * b0,
+            b2: b0,
+            a1: -2.0 * cos_w0 / a0,
+            a2: (1.0 - alpha) / a0,
+        }
+    }
 }
 
-fn stereo_to_mono(samples: &[f32], channels: usize) -> Vec<f32> {
+impl Processor for HighPass {
+    fn process(&mut self, samples: &mut [f32]) -> Result<()> {
+        // Transposed direct form II
+        let (mut z1, mut z2) = (0.0f32, 0.0f32);
+        for sample in samples.iter_mut() {
+            let x = *sample;
+            let y = self.b0 * x + z1;
+            z1 = self.b1 * x - self.a1 * y + z2;
+            z2 = self.b2 * x - self.a2 * y;
+            *sample = y;
+        }
+        Ok(())
+    }
+}
+
+/// Frame-based noise gate with hold time and click-free gain ramps
+#[derive(Default)]
+struct NoiseGate {
+    gain: f32,
+}
+
+impl Processor for NoiseGate {
+    fn process(&mut self, samples: &mut [f32]) -> Result<()> {
+        self.gain = GATE_FLOOR_GAIN;
+        let mut hold = 0;
+
+        for frame in samples.chunks_mut(GATE_FRAME_LEN) {
+            let sum_squares: f32 = frame.iter().map(|s| s * s).sum();
+            #[allow(clippy::cast_precision_loss)]
+            let rms = (sum_squares / frame.len() as f32).sqrt();
+
+            if rms > GATE_THRESHOLD_RMS {
+                hold = GATE_HOLD_FRAMES;
+            } else {
+                hold = hold.saturating_sub(1);
+            }
+            let target = if hold > 0 { 1.0 } else { GATE_FLOOR_GAIN };
+
+            // Ramp linearly across the frame to avoid clicks
+            #[allow(clippy::cast_precision_loss)]
+            let step = (target - self.gain) / frame.len() as f32;
+            for sample in frame.iter_mut() {
+                self.gain += step;
+                *sample *= self.gain;
+            }
+            self.gain = target;
+        }
+        Ok(())
+    }
+}
+
+/// Spectral subtraction using a noise profile taken from the quietest frames
+struct SpectralDenoise;
+
+impl Processor for SpectralDenoise {
+    fn process(&mut self, samples: &mut [f32]) -> Result<()> {
+        const HOP: usize = DENOISE_FRAME_LEN / 2;
+        if samples.len() < DENOISE_FRAME_LEN {
+            return Ok(());
+        }
+
+        let mut planner = RealFftPlanner::<f32>::new();
+        let forward = planner.plan_fft_forward(DENOISE_FRAME_LEN);
+        let inverse = planner.plan_fft_inverse(DENOISE_FRAME_LEN);
+
+        // Square root of a periodic Hann window, applied on analysis and synthesis,
+        // so overlapping frames at 50% sum back to unity
+        #[allow(clippy::cast_precision_loss)]
+        let window: Vec<f32> = (0..DENOISE_FRAME_LEN)
+            .map(|i| (std::f32::consts::PI * i as f32 / DENOISE_FRAME_LEN as f32).sin())
+            .collect();
+
+        // Pad by a hop on both sides so every sample is covered by two frames
+        let padded_len = (samples.len() + 2 * HOP).div_ceil(HOP) * HOP + HOP;
+        let mut padded = vec![0.0f32; padded_len];
+        padded[HOP..HOP + samples.len()].copy_from_slice(samples);
+
+        let mut frame = forward.make_input_vec();
+        let mut spectra = Vec::new();
+        for start in (0..=padded_len - DENOISE_FRAME_LEN).step_by(HOP) {
+            for ((out, x), w) in frame
+                .iter_mut()
+                .zip(&padded[start..start + DENOISE_FRAME_LEN])
+                .zip(&window)
+            {
+                *out = x * w;
+            }
+            let mut spectrum = forward.make_output_vec();
+            forward
+                .process(&mut frame, &mut spectrum)
+                .map_err(|e| Error::Audio(format!("denoise FFT failed: {e}")))?;
+            spectra.push(spectrum);
+        }
+
+        let noise = noise_profile(&spectra);
+
+        let mut output = vec![0.0f32; padded_len];
+        #[allow(clippy::cast_precision_loss)]
+        let scale = 1.0 / DENOISE_FRAME_LEN as f32;
+        for (index, spectrum) in spectra.iter_mut().enumerate() {
+            for (bin, noise_mag) in spectrum.iter_mut().zip(&noise) {
+                let mag = bin.norm();
+                let gain = if mag > f32::EPSILON {
+                    (1.0 - DENOISE_OVERSUBTRACTION * noise_mag / mag).max(DENOISE_FLOOR_GAIN)
+                } else {
+                    DENOISE_FLOOR_GAIN
+                };
+                *bin *= gain;
+            }
+            // DC and Nyquist must stay real for the inverse transform
+            spectrum[0].im = 0.0;
+            spectrum[DENOISE_FRAME_LEN / 2].im = 0.0;
+
+            inverse
+                .process(spectrum, &mut frame)
+                .map_err(|e| Error::Audio(format!("denoise FFT failed: {e}")))?;
+
+            let start = index * HOP;
+            for ((out, x), w) in output[start..start + DENOISE_FRAME_LEN]
+                .iter_mut()
+                .zip(&frame)
+                .zip(&window)
+            {
+                *out += x * w * scale;
+            }
+        }
+
+        samples.copy_from_slice(&output[HOP..HOP + samples.len()]);
+        Ok(())
+    }
+}
+
+/// Average magnitude per bin over the quietest frames.
+fn noise_profile(spectra: &[Vec<Complex<f32>>]) -> Vec<f32> {
+    let energy = |spectrum: &Vec<Complex<f32>>| spectrum.iter().map(Complex::norm_sqr).sum::<f32>();
+    let mut order: Vec<usize> = (0..spectra.len()).collect();
+    order.sort_by(|&a, &b| energy(&spectra[a]).total_cmp(&energy(&spectra[b])));
+
+    let quiet = &order[..(spectra.len() / DENOISE_NOISE_FRACTION).max(1)];
+    let mut profile = vec![0.0f32; spectra[0].len()];
+    for &index in quiet {
+        for (acc, bin) in profile.iter_mut().zip(&spectra[index]) {
+            *acc += bin.norm();
+        }
+    }
     #[allow(clippy::cast_precision_loss)]
-    let channels_f32 = channels as f32;
+    let count = quiet.len() as f32;
+    for acc in &mut profile {
+        *acc /= count;
+    }
+    profile
+}
+
+/// The settings that turn raw 16kHz recordings into audio for Whisper
+#[derive(Debug, Clone)]
+pub struct Conditioning {
+    pub dsp: DspSettings,
+    pub gain_mode: GainMode,
+    pub loopback: LoopbackSettings,
+}
+
+impl Conditioning {
+    /// Condition the microphone and system audio, then mix them. The system audio
+    /// starts `offset` samples into the microphone recording.
+    pub fn apply(
+        &self,
+        mic: Option<Vec<f32>>,
+        loopback: Option<(Vec<f32>, usize)>,
+    ) -> Result<Vec<f32>> {
+        let mic = match mic {
+            Some(mut output) => {
+                eprintln!("[Samples collected: {} at 16kHz]", output.len());
+                let gain = condition(&mut output, self.dsp, self.gain_mode)?;
+                eprintln!("[Applied gain: {gain:.2} ({:?})]", self.gain_mode);
+                Some(output)
+            }
+            None => None,
+        };
+
+        Ok(match (mic, loopback) {
+            (Some(mic), None) => mic,
+            (mic, Some((mut loopback, offset))) => {
+                eprintln!("[System audio collected: {} at 16kHz]", loopback.len());
+                // A quiet call is normalised like the microphone; the mix gains apply on top
+                let gain = condition(&mut loopback, self.dsp, GainMode::Auto)?;
+                eprintln!("[Applied system audio gain: {gain:.2}]");
+                mix(
+                    mic.as_deref().unwrap_or_default(),
+                    &loopback,
+                    offset,
+                    &self.loopback,
+                )
+            }
+            (None, None) => Vec::new(),
+        })
+    }
+}
+
+/// Prepare 16kHz mono audio for Whisper: the enabled DSP stages, then the gain.
+/// Returns the gain used.
+pub fn condition(samples: &mut [f32], dsp: DspSettings, gain_mode: GainMode) -> Result<f32> {
+    // Condition the signal before gain so rumble and noise don't skew the AGC peak
+    preprocess(samples, dsp)?;
+    Ok(apply_gain(samples, gain_mode))
+}
+
+/// Amplify samples in place according to the gain mode, returning the gain used.
+fn apply_gain(samples: &mut [f32], gain_mode: GainMode) -> f32 {
+    let gain = match gain_mode {
+        GainMode::Auto => auto_gain(samples),
+        GainMode::Manual(gain) => gain,
+    };
+
+    for sample in samples.iter_mut() {
+        *sample = (*sample * gain).clamp(-1.0, 1.0);
+    }
+
+    gain
+}
+
+/// Compute a peak-normalising gain for a recording.
+fn auto_gain(samples: &[f32]) -> f32 {
+    let peak = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+    if peak <= f32::EPSILON {
+        return 1.0;
+    }
+    (AGC_TARGET_PEAK / peak).clamp(AGC_MIN_GAIN, AGC_MAX_GAIN)
+}
+
+/// Mix 16kHz mono system audio that started `offset` samples into the microphone
+/// recording, applying each source's gain. Pass an empty `mic` for system audio alone.
+fn mix(mic: &[f32], loopback: &[f32], offset: usize, settings: &LoopbackSettings) -> Vec<f32> {
+    let offset = if mic.is_empty() { 0 } else { offset };
+    let len = mic.len().max(offset + loopback.len());
+    (0..len)
+        .map(|i| {
+            let mic_sample = mic.get(i).copied().unwrap_or(0.0);
+            let loopback_sample = i
+                .checked_sub(offset)
+                .and_then(|j| loopback.get(j))
+                .copied()
+                .unwrap_or(0.0);
+            (mic_sample * settings.mic_gain + loopback_sample * settings.loopback_gain)
+                .clamp(-1.0, 1.0)
+        })
+        .collect()
+}
+
+/// Average the `selected` channels of each interleaved frame into a mono sample.
+fn downmix(samples: &[f32], channels: usize, selected: &[usize]) -> Vec<f32> {
+    #[allow(clippy::cast_precision_loss)]
+    let selected_f32 = selected.len() as f32;
     samples
         .chunks(channels)
-        .map(|frame| frame.iter().sum::<f32>() / channels_f32)
+        .map(|frame| selected.iter().map(|&c| frame[c]).sum::<f32>() / selected_f32)
         .collect()
 }
 
-fn resample(samples: &[f32], from_rate: u32, to_rate: u32) -> Result<Vec<f32>> {
+/// Convert interleaved audio at any rate into 16kHz mono, e.g. after decoding a file.
+pub fn to_whisper_format(
+    interleaved: &[f32],
+    sample_rate: u32,
+    channels: usize,
+) -> Result<Vec<f32>> {
+    let mono = if channels > 1 {
+        downmix(
+            interleaved,
+            channels,
+            &ChannelSelection::Average.indices(channels),
+        )
+    } else {
+        interleaved.to_vec()
+    };
+
+    if sample_rate == WHISPER_SAMPLE_RATE {
+        Ok(mono)
+    } else {
+        resample(&mono, sample_rate, WHISPER_SAMPLE_RATE)
+    }
+}
+
+/// Resample a whole buffer at once. Matches the output of `ChunkResampler`.
+pub fn resample(samples: &[f32], from_rate: u32, to_rate: u32) -> Result<Vec<f32>> {
     use audioadapter_buffers::direct::SequentialSliceOfVecs;
 
     // Use a reasonable chunk size for the resampler
@@ -413,3 +1874,332 @@ fn resample(samples: &[f32], from_rate: u32, to_rate: u32) -> Result<Vec<f32>> {
     eprintln!("[Resampled {} -> {} samples]", samples.len(), output.len());
     Ok(output)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_signal::{sine_wave, SignalSource};
+
+    #[test]
+    fn test_auto_gain_normalises_peak() {
+        let mut samples = vec![0.0, 0.1, -0.3, 0.2];
+        let gain = apply_gain(&mut samples, GainMode::Auto);
+        assert!((gain - 3.0).abs() < 1e-5);
+        assert!((samples[2] + AGC_TARGET_PEAK).abs() < 1e-5);
+
+        // Very quiet input is capped at the maximum gain
+        let mut quiet = vec![0.001, -0.001];
+        assert!((apply_gain(&mut quiet, GainMode::Auto) - AGC_MAX_GAIN).abs() < 1e-5);
+
+        // Silence is left untouched
+        let mut silence = vec![0.0; 4];
+        assert!((apply_gain(&mut silence, GainMode::Auto) - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_manual_gain_clamps_output() {
+        let mut samples = vec![0.5, -0.5, 0.1];
+        apply_gain(&mut samples, GainMode::manual(4.0));
+        assert_eq!(samples, vec![1.0, -1.0, 0.4]);
+
+        assert_eq!(GainMode::manual(100.0), GainMode::Manual(MANUAL_GAIN_MAX));
+    }
+
+    #[test]
+    fn test_preroll_keeps_most_recent_frames() {
+        let capacity = preroll_capacity(1, 4000, 2);
+        assert_eq!(capacity, 8);
+
+        let (mut producer, consumer) = RingBuffer::new(32);
+        let mut buffers = CaptureBuffers {
+            consumer: Some(consumer),
+            pipeline: CapturePipeline::new(WHISPER_SAMPLE_RATE, 2, &ChannelSelection::Average)
+                .unwrap(),
+            preroll: VecDeque::new(),
+            preroll_capacity: capacity,
+            meter_window: VecDeque::new(),
+        };
+
+        for i in 0..6 {
+            producer.push(i as f32).unwrap();
+        }
+        buffers.drain(false);
+        for i in 6..12 {
+            producer.push(i as f32).unwrap();
+        }
+        buffers.drain(false);
+
+        let preroll: Vec<f32> = buffers.preroll.iter().copied().collect();
+        assert_eq!(preroll, (4..12).map(|i| i as f32).collect::<Vec<_>>());
+
+        // While recording, drained frames go to the (downmixed) recording instead
+        producer.push(12.0).unwrap();
+        producer.push(13.0).unwrap();
+        buffers.drain(true);
+        assert_eq!(buffers.pipeline.finish().unwrap(), vec![12.5]);
+
+        // The meter sees every drained frame, downmixed, whether recording or not
+        let meter: Vec<f32> = buffers.meter_window.iter().copied().collect();
+        assert_eq!(meter, vec![0.5, 2.5, 4.5, 6.5, 8.5, 10.5, 12.5]);
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn test_resample_preserves_length_and_tone() {
+        let input = sine_wave(440.0, 0.5, 48000, 48000);
+        let output = resample(&input, 48000, WHISPER_SAMPLE_RATE).unwrap();
+
+        // One second in, one second out
+        assert!(output.len().abs_diff(16000) <= 1, "len = {}", output.len());
+
+        // Amplitude is preserved away from the edges
+        let middle = &output[2000..14000];
+        assert!((rms(middle) - 0.5 / 2f32.sqrt()).abs() < 0.01);
+
+        // Frequency is preserved: 440Hz crosses zero ~880 times per second
+        let crossings = middle
+            .windows(2)
+            .filter(|w| (w[0] < 0.0) != (w[1] < 0.0))
+            .count();
+        let expected = 880.0 * middle.len() as f32 / 16000.0;
+        assert!((crossings as f32 - expected).abs() < 5.0);
+    }
+
+    #[test]
+    fn test_chunk_resampler_matches_whole_buffer_resample() {
+        let input: Vec<f32> = sine_wave(300.0, 0.5, 44100, 30000)
+            .iter()
+            .zip(sine_wave(1234.0, 0.5, 44100, 30000))
+            .map(|(a, b)| a + b * 0.5)
+            .collect();
+        let expected = resample(&input, 44100, WHISPER_SAMPLE_RATE).unwrap();
+
+        // Feed audio in uneven pieces, like callback buffers
+        let mut chunked = ChunkResampler::new(44100, WHISPER_SAMPLE_RATE).unwrap();
+        for piece in input.chunks(333) {
+            chunked.push(piece).unwrap();
+        }
+        let output = chunked.finish().unwrap();
+
+        assert!(output.len().abs_diff(expected.len()) <= 1);
+        let max_diff = output
+            .iter()
+            .zip(&expected)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0f32, f32::max);
+        assert!(max_diff < 1e-4, "max_diff = {max_diff}");
+
+        // The resampler is reset and reusable after finishing
+        chunked.push(&input).unwrap();
+        assert_eq!(chunked.finish().unwrap().len(), output.len());
+    }
+
+    #[test]
+    fn test_pipeline_downmixes_channels() {
+        let mut pipeline =
+            CapturePipeline::new(WHISPER_SAMPLE_RATE, 2, &ChannelSelection::Average).unwrap();
+        pipeline.push(&[1.0, 0.0, 0.5, 0.5, -1.0, 1.0]);
+        assert_eq!(pipeline.finish().unwrap(), vec![0.5, 0.5, 0.0]);
+        assert!(pipeline.finish().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_pipeline_stops_at_length_limit() {
+        let mut pipeline =
+            CapturePipeline::new(WHISPER_SAMPLE_RATE, 2, &ChannelSelection::Average).unwrap();
+        pipeline.set_max_samples(3);
+        pipeline.push(&[0.5; 4]);
+        assert!(!pipeline.is_full());
+        pipeline.push(&[0.5; 4]);
+        assert!(pipeline.is_full());
+        pipeline.push(&[0.5; 4]);
+        assert_eq!(pipeline.finish().unwrap().len(), 3);
+        assert!(!pipeline.is_full());
+    }
+
+    #[test]
+    fn test_channel_selection_picks_inputs() {
+        // Two frames of a 4-channel interface
+        let frames = [0.8, 0.1, 0.2, 0.3, -0.4, 0.1, 0.0, 0.3];
+
+        let single = ChannelSelection::Single(0).indices(4);
+        assert_eq!(downmix(&frames, 4, &single), vec![0.8, -0.4]);
+
+        let subset = ChannelSelection::Subset(vec![3, 1]).indices(4);
+        assert_eq!(subset, vec![1, 3]);
+        assert_eq!(downmix(&frames, 4, &subset), vec![0.2, 0.2]);
+
+        // Channels the device doesn't have fall back to averaging everything
+        assert_eq!(ChannelSelection::Single(6).indices(4), vec![0, 1, 2, 3]);
+        assert_eq!(ChannelSelection::Average.indices(2), vec![0, 1]);
+    }
+
+    /// Deterministic white noise in [-amplitude, amplitude]
+    fn white_noise(len: usize, amplitude: f32) -> Vec<f32> {
+        let mut state = 0x1234_5678u32;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 8) as f32 / (1u32 << 24) as f32 * 2.0 * amplitude - amplitude
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_high_pass_removes_rumble() {
+        let second = WHISPER_SAMPLE_RATE as usize;
+        let settings = DspSettings {
+            high_pass: true,
+            ..DspSettings::default()
+        };
+
+        let mut rumble = sine_wave(20.0, 0.5, WHISPER_SAMPLE_RATE, second);
+        preprocess(&mut rumble, settings).unwrap();
+        assert!(rms(&rumble[second / 2..]) < 0.05);
+
+        let mut voice = sine_wave(1000.0, 0.5, WHISPER_SAMPLE_RATE, second);
+        preprocess(&mut voice, settings).unwrap();
+        assert!((rms(&voice[second / 2..]) - 0.5 / 2f32.sqrt()).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_noise_gate_attenuates_quiet_stretches() {
+        // 1s of faint noise followed by 1s of tone
+        let second = WHISPER_SAMPLE_RATE as usize;
+        let mut samples = white_noise(second, 0.002);
+        samples.extend(sine_wave(440.0, 0.3, WHISPER_SAMPLE_RATE, second));
+        let original = samples.clone();
+
+        let settings = DspSettings {
+            noise_gate: true,
+            ..DspSettings::default()
+        };
+        preprocess(&mut samples, settings).unwrap();
+
+        let noise_ratio = rms(&samples[..second]) / rms(&original[..second]);
+        assert!((noise_ratio - GATE_FLOOR_GAIN).abs() < 0.01);
+        // Skip the first frame of the tone while the gate ramps open
+        let tone_ratio =
+            rms(&samples[second + GATE_FRAME_LEN..]) / rms(&original[second + GATE_FRAME_LEN..]);
+        assert!((tone_ratio - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_denoise_suppresses_stationary_noise() {
+        // 1s of noise, then 1s of tone over the same noise
+        let second = WHISPER_SAMPLE_RATE as usize;
+        let noise = white_noise(2 * second, 0.05);
+        let tone = sine_wave(440.0, 0.3, WHISPER_SAMPLE_RATE, second);
+        let mut samples = noise.clone();
+        for (s, t) in samples[second..].iter_mut().zip(&tone) {
+            *s += t;
+        }
+
+        let settings = DspSettings {
+            denoise: true,
+            ..DspSettings::default()
+        };
+        preprocess(&mut samples, settings).unwrap();
+
+        // Noise-only part is strongly reduced
+        assert!(rms(&samples[..second]) < rms(&noise[..second]) * 0.3);
+        // The tone survives: residual error against the clean tone is well below the noise
+        let error: Vec<f32> = samples[second..]
+            .iter()
+            .zip(&tone)
+            .map(|(s, t)| s - t)
+            .collect();
+        assert!(rms(&error) < rms(&noise[second..]) * 0.5);
+    }
+
+    #[test]
+    fn test_disabled_chain_is_passthrough() {
+        let mut samples = white_noise(4000, 0.5);
+        let original = samples.clone();
+        preprocess(&mut samples, DspSettings::default()).unwrap();
+        assert_eq!(samples, original);
+    }
+
+    #[test]
+    fn test_mix_aligns_and_scales_sources() {
+        let settings = LoopbackSettings {
+            device: None,
+            mic_gain: 0.5,
+            loopback_gain: 2.0,
+        };
+        // The system audio starts after two samples of pre-roll and outlasts the microphone
+        let mixed = mix(&[0.2, 0.2, 0.2], &[0.1, 0.3, 0.1], 2, &settings);
+        let expected = [0.1, 0.1, 0.3, 0.6, 0.2];
+        assert_eq!(mixed.len(), expected.len());
+        for (actual, expected) in mixed.iter().zip(expected) {
+            assert!((actual - expected).abs() < 1e-6, "{mixed:?}");
+        }
+
+        // System audio alone ignores the offset and is clamped to full scale
+        assert_eq!(mix(&[], &[0.3, 0.6], 2, &settings), vec![0.6, 1.0]);
+    }
+
+    #[test]
+    fn test_discarded_recording_is_not_carried_over() {
+        let tone = SignalSource::sine(440.0, 0.5, Duration::from_secs(2), WHISPER_SAMPLE_RATE, 1);
+        let clock = tone.clock();
+        let mut recorder = AudioRecorder::with_source(
+            Box::new(tone),
+            GainMode::manual(1.0),
+            0,
+            ChannelSelection::Average,
+            DspSettings::default(),
+            60,
+        )
+        .unwrap();
+        recorder.set_trailing_ms(0);
+
+        recorder.start(CaptureMode::Microphone, None).unwrap();
+        clock.advance(4800);
+        recorder.discard();
+
+        // The next recording starts empty
+        recorder.start(CaptureMode::Microphone, None).unwrap();
+        clock.advance(1600);
+        let audio = recorder.stop().unwrap();
+        assert_eq!(audio.len(), 1600);
+    }
+
+    #[test]
+    fn test_loopback_mode_records_system_audio_only() {
+        let second = Duration::from_secs(1);
+        let mic = SignalSource::new("mic", vec![0.0; 16000], WHISPER_SAMPLE_RATE, 1);
+        // A quiet call
+        let loopback = SignalSource::sine(440.0, 0.1, second, WHISPER_SAMPLE_RATE, 2);
+        let clock = loopback.clock();
+        let mut recorder = AudioRecorder::with_source(
+            Box::new(mic),
+            GainMode::manual(1.0),
+            0,
+            ChannelSelection::Average,
+            DspSettings::default(),
+            60,
+        )
+        .unwrap();
+        recorder.set_trailing_ms(0);
+
+        // Loopback mode needs a source to record from
+        assert!(recorder
+            .start_with_loopback(CaptureMode::Loopback, None, None)
+            .is_err());
+
+        recorder
+            .start_with_loopback(CaptureMode::Loopback, Some(Box::new(loopback)), None)
+            .unwrap();
+        clock.advance(4800);
+        let audio = recorder.stop().unwrap();
+
+        assert_eq!(audio.len(), 4800);
+        // Normalised like the microphone, and the silent microphone isn't mixed in
+        let middle = &audio[800..audio.len() - 800];
+        assert!((rms(middle) - AGC_TARGET_PEAK / 2f32.sqrt()).abs() < 0.05);
+    }
+}
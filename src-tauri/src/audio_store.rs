@@ -0,0 +1,198 @@
+//! Optional on-disk storage of recorded audio, linked to history entries by id.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Result};
+use crate::transcribe::WHISPER_SAMPLE_RATE;
+
+/// Subdirectory of the app data dir holding saved recordings
+const RECORDINGS_DIR: &str = "recordings";
+
+/// Default storage quota for saved recordings
+pub const DEFAULT_AUDIO_QUOTA_MB: u32 = 500;
+
+/// Smallest storage quota, so a stored zero doesn't delete every recording as it is saved
+pub const MIN_AUDIO_QUOTA_MB: u32 = 1;
+
+/// Stores 16kHz mono recordings as 16-bit WAV files named after the transcription id
+pub struct AudioStore {
+    dir: PathBuf,
+}
+
+impl AudioStore {
+    /// Open the recordings directory, creating it if needed
+    pub fn new(app_data_dir: &Path) -> Result<Self> {
+        let dir = app_data_dir.join(RECORDINGS_DIR);
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| Error::Storage(format!("failed to create recordings dir: {e}")))?;
+
+        // Recordings are as sensitive as the history itself (owner access only)
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let _ = std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700));
+        }
+
+        Ok(Self { dir })
+    }
+
+    /// Path of the recording for a transcription id
+    pub fn path_for(&self, id: i64) -> PathBuf {
+        self.dir.join(format!("{id}.wav"))
+    }
+
+    /// Write the recording for a transcription, returning its path.
+    pub fn save(&self, id: i64, samples: &[f32]) -> Result<PathBuf> {
+        let path = self.path_for(id);
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: WHISPER_SAMPLE_RATE,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+
+        let mut writer = hound::WavWriter::create(&path, spec)
+            .map_err(|e| Error::Storage(format!("failed to create {}: {e}", path.display())))?;
+        for &sample in samples {
+            #[allow(clippy::cast_possible_truncation)]
+            let value = (sample.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16;
+            writer
+                .write_sample(value)
+                .map_err(|e| Error::Storage(format!("failed to write recording: {e}")))?;
+        }
+        writer
+            .finalize()
+            .map_err(|e| Error::Storage(format!("failed to finalize recording: {e}")))?;
+
+        Ok(path)
+    }
+
+    /// Delete the recording for a transcription. A missing file is not an error.
+    pub fn delete(&self, id: i64) -> Result<()> {
+        match std::fs::remove_file(self.path_for(id)) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(Error::Storage(format!(
+                "failed to delete recording {id}: {e}"
+            ))),
+        }
+    }
+
+    /// Delete recordings whose entry is gone from `history_ids`.
+    /// Returns the ids of recordings that were deleted.
+    pub fn remove_orphans(&self, history_ids: &HashSet<i64>) -> Result<Vec<i64>> {
+        Ok(self
+            .list()?
+            .into_iter()
+            .map(|(id, _)| id)
+            .filter(|id| !history_ids.contains(id) && self.delete(*id).is_ok())
+            .collect())
+    }
+
+    /// Delete recordings whose entry is gone from `history_ids`, then the oldest
+    /// recordings until the total size fits in `quota_bytes`.
+    /// Returns the ids of recordings that were deleted.
+    pub fn enforce_quota(&self, quota_bytes: u64, history_ids: &HashSet<i64>) -> Result<Vec<i64>> {
+        let mut deleted = self.remove_orphans(history_ids)?;
+        let mut recordings = self.list()?;
+
+        // Ids grow monotonically, so the lowest id is the oldest recording
+        recordings.sort_unstable_by_key(|&(id, _)| id);
+        let mut total: u64 = recordings.iter().map(|&(_, size)| size).sum();
+        for (id, size) in recordings {
+            if total <= quota_bytes {
+                break;
+            }
+            self.delete(id)?;
+            total -= size;
+            deleted.push(id);
+        }
+
+        Ok(deleted)
+    }
+
+    /// All saved recordings as (transcription id, size in bytes).
+    fn list(&self) -> Result<Vec<(i64, u64)>> {
+        let entries = std::fs::read_dir(&self.dir)
+            .map_err(|e| Error::Storage(format!("failed to read recordings dir: {e}")))?;
+
+        Ok(entries
+            .filter_map(std::result::Result::ok)
+            .filter_map(|entry| {
+                let path = entry.path();
+                if path.extension()? != "wav" {
+                    return None;
+                }
+                let id = path.file_stem()?.to_str()?.parse().ok()?;
+                let size = entry.metadata().ok()?.len();
+                Some((id, size))
+            })
+            .collect())
+    }
+}
+
+/// Read a WAV file as interleaved samples between -1.0 and 1.0.
+pub fn read_wav(path: &Path) -> Result<(Vec<f32>, hound::WavSpec)> {
+    let mut reader = hound::WavReader::open(path)
+        .map_err(|e| Error::Storage(format!("failed to open {}: {e}", path.display())))?;
+    let spec = reader.spec();
+
+    #[allow(clippy::cast_precision_loss)]
+    let samples = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect(),
+        hound::SampleFormat::Int => {
+            let scale = (1u32 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|s| s.map(|s| s as f32 / scale))
+                .collect::<std::result::Result<Vec<_>, _>>()
+        }
+    }
+    .map_err(|e| Error::Storage(format!("failed to read {}: {e}", path.display())))?;
+
+    Ok((samples, spec))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_save_and_delete_recording() {
+        let temp_dir = tempdir().unwrap();
+        let store = AudioStore::new(temp_dir.path()).unwrap();
+
+        let path = store.save(7, &[0.0, 0.5, -0.5, 1.0]).unwrap();
+        let reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec().sample_rate, WHISPER_SAMPLE_RATE);
+        assert_eq!(reader.len(), 4);
+
+        store.delete(7).unwrap();
+        assert!(!path.exists());
+        // Deleting again is fine
+        store.delete(7).unwrap();
+    }
+
+    #[test]
+    fn test_quota_removes_orphans_then_oldest() {
+        let temp_dir = tempdir().unwrap();
+        let store = AudioStore::new(temp_dir.path()).unwrap();
+
+        let second = vec![0.1f32; WHISPER_SAMPLE_RATE as usize];
+        for id in 1..=4 {
+            store.save(id, &second).unwrap();
+        }
+        let size = std::fs::metadata(store.path_for(1)).unwrap().len();
+
+        // Entry 1 is no longer in history; only two recordings fit the quota
+        let history_ids = HashSet::from([2, 3, 4]);
+        let deleted = store.enforce_quota(size * 2, &history_ids).unwrap();
+
+        assert_eq!(deleted, vec![1, 2]);
+        assert!(!store.path_for(2).exists());
+        assert!(store.path_for(3).exists());
+        assert!(store.path_for(4).exists());
+    }
+}
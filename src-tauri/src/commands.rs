@@ -1,27 +1,108 @@
 //! Tauri command handlers for the frontend.
 
+use std::path::Path;
 use std::sync::{Arc, Mutex};
 
-use tauri::Manager;
+use serde::Serialize;
+use tauri::{Emitter, Manager};
 use tauri_plugin_global_shortcut::GlobalShortcutExt;
 use tauri_plugin_store::StoreExt;
 
+use crate::audio::AudioDeviceInfo;
+use crate::audio_store::AudioStore;
 use crate::history::{HistoryDb, Transcription};
-use crate::settings::AppSettings;
+use crate::mic_test::{MicTest, SAMPLE_PLAYBACK_ID};
+use crate::playback::{PlaybackStatus, Player};
+use crate::recording::store_transcription;
+use crate::settings::{load_capture_format, AppSettings};
 use crate::shortcuts::register_all_shortcuts;
-use crate::transcribe::Transcriber;
-use crate::warmup;
+use crate::spill::{OrphanedRecording, SpillStore};
+use crate::transcribe::{Language, LanguageChoice, TranscribeOptions, Transcriber};
+use crate::vocabulary::Vocabulary;
 use crate::AppResources;
+use crate::{vad, warmup};
 
 #[tauri::command]
-pub fn list_audio_devices() -> Vec<String> {
+pub fn list_audio_devices() -> Vec<AudioDeviceInfo> {
     crate::audio::list_input_devices()
 }
 
 #[tauri::command]
 #[allow(clippy::needless_pass_by_value)] // Tauri commands require owned parameters
-pub fn validate_audio_device(device_name: Option<String>) -> bool {
-    crate::audio::device_exists(device_name.as_deref())
+pub fn validate_audio_device(device_id: Option<String>) -> bool {
+    crate::audio::device_exists(device_id.as_deref())
+}
+
+#[tauri::command]
+pub fn list_output_devices() -> Vec<AudioDeviceInfo> {
+    crate::audio::list_output_devices()
+}
+
+#[tauri::command]
+pub fn list_loopback_devices() -> Vec<AudioDeviceInfo> {
+    crate::audio::list_loopback_devices()
+}
+
+/// Every language Whisper can transcribe
+#[tauri::command]
+pub fn list_languages() -> Vec<Language> {
+    Language::all().collect()
+}
+
+/// Start the microphone test on a device, replacing a running one.
+/// Returns the name of the device that was opened.
+#[tauri::command]
+pub async fn start_mic_test(
+    app: tauri::AppHandle,
+    device_id: Option<String>,
+) -> Result<String, String> {
+    let store = app
+        .store("settings.json")
+        .map_err(|e| format!("Failed to open store: {e}"))?;
+    let capture_format = load_capture_format(&store);
+
+    let mic_test = app.state::<Mutex<Option<MicTest>>>();
+    let mut mic_test = mic_test.lock().unwrap();
+    // Release the previous stream before opening the device again
+    *mic_test = None;
+    let test = MicTest::start(&app, device_id.as_deref(), capture_format)
+        .map_err(|e| format!("Failed to start microphone test: {e}"))?;
+    let device_name = test.device_name().to_string();
+    *mic_test = Some(test);
+    Ok(device_name)
+}
+
+#[tauri::command]
+pub async fn stop_mic_test(app: tauri::AppHandle) {
+    app.state::<Mutex<Option<MicTest>>>().lock().unwrap().take();
+}
+
+#[tauri::command]
+pub async fn record_mic_test_sample(app: tauri::AppHandle) -> Result<(), String> {
+    let mic_test = app.state::<Mutex<Option<MicTest>>>();
+    let mic_test = mic_test.lock().unwrap();
+    let test = mic_test
+        .as_ref()
+        .ok_or_else(|| "Microphone test is not running".to_string())?;
+    test.record_sample();
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn play_mic_test_sample(app: tauri::AppHandle) -> Result<(), String> {
+    let sample = app
+        .state::<Mutex<Option<MicTest>>>()
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(MicTest::sample)
+        .ok_or_else(|| "No test sample recorded".to_string())?;
+
+    let player = app.state::<Mutex<Player>>();
+    let mut player = player.lock().unwrap();
+    player
+        .play_samples(&app, SAMPLE_PLAYBACK_ID, &sample)
+        .map_err(|e| format!("Failed to play test sample: {e}"))
 }
 
 #[tauri::command]
@@ -59,17 +140,39 @@ pub async fn reload_settings(app: tauri::AppHandle) -> Result<(), String> {
     {
         let resources = app.state::<Arc<Mutex<AppResources>>>();
         let mut res = resources.lock().unwrap();
+        res.recorder.set_capture_format(settings.capture_format);
         if let Err(e) = res.recorder.set_device(settings.audio_device.as_deref()) {
             eprintln!("[Failed to switch audio device: {e}]");
             return Err(format!("Failed to switch audio device: {e}"));
         }
+        res.recorder.set_gain_mode(settings.input_gain);
+        res.recorder.set_preroll_ms(settings.preroll_ms);
+        res.recorder
+            .set_channel_selection(settings.input_channels.clone());
+        res.recorder.set_dsp(settings.dsp);
+        res.recorder.set_loopback(settings.loopback.clone());
+        res.recorder
+            .set_max_recording_secs(settings.max_recording_secs);
+        res.recorder.set_trailing_ms(settings.trailing_ms);
+        res.min_press_ms = settings.min_press_ms;
         // Update hotkey settings for tray tooltips
-        res.hotkey_en.clone_from(&settings.hotkey_en);
+        res.hotkey_record = settings.primary_hotkey().to_string();
         res.hotkey_mute.clone_from(&settings.hotkey_mute);
         // Update output mode
         res.output_mode = settings.output_mode.clone();
+        res.detect_languages.clone_from(&settings.detect_languages);
+        res.vocabulary = settings.vocabulary.clone();
+        res.decoding = settings.decoding;
+        res.save_audio = settings.save_audio;
+        res.audio_quota_mb = settings.audio_quota_mb;
+        res.meter_rate_hz = settings.meter_rate_hz;
     }
 
+    app.state::<Mutex<Player>>()
+        .lock()
+        .unwrap()
+        .set_device(settings.playback_device.clone());
+
     // Re-register all shortcuts with new hotkeys
     register_all_shortcuts(&app, &settings)?;
 
@@ -112,7 +215,356 @@ pub async fn get_history(app: tauri::AppHandle) -> Result<Vec<Transcription>, St
 #[tauri::command]
 pub async fn delete_transcription(app: tauri::AppHandle, id: i64) -> Result<bool, String> {
     let history_db = app.state::<Arc<HistoryDb>>();
-    history_db
+    let deleted = history_db
         .delete_transcription(id)
-        .map_err(|e| format!("Failed to delete transcription: {e}"))
+        .map_err(|e| format!("Failed to delete transcription: {e}"))?;
+
+    // Remove the saved recording too, if there is one
+    {
+        let player = app.state::<Mutex<Player>>();
+        let mut player = player.lock().unwrap();
+        if player.current_id() == Some(id) {
+            player.stop();
+        }
+    }
+    // The entry is gone either way; a leftover file is removed with the next orphans
+    if let Err(e) = app.state::<Arc<AudioStore>>().delete(id) {
+        eprintln!("[Failed to delete recording {id}: {e}]");
+    }
+
+    Ok(deleted)
+}
+
+#[tauri::command]
+pub async fn playback_play(app: tauri::AppHandle, id: i64) -> Result<(), String> {
+    let path = app.state::<Arc<AudioStore>>().path_for(id);
+    if !path.exists() {
+        return Err(format!("No saved recording for transcription {id}"));
+    }
+
+    let player = app.state::<Mutex<Player>>();
+    let mut player = player.lock().unwrap();
+    player
+        .play(&app, id, &path)
+        .map_err(|e| format!("Failed to play recording: {e}"))
+}
+
+#[tauri::command]
+pub async fn playback_pause(app: tauri::AppHandle) {
+    let status = app.state::<Mutex<Player>>().lock().unwrap().pause();
+    if let Some(status) = status {
+        let _ = app.emit("playback-position", status);
+    }
+}
+
+#[tauri::command]
+pub async fn playback_seek(app: tauri::AppHandle, position_ms: u64) {
+    let status = app
+        .state::<Mutex<Player>>()
+        .lock()
+        .unwrap()
+        .seek(position_ms);
+    if let Some(status) = status {
+        let _ = app.emit("playback-position", status);
+    }
+}
+
+#[tauri::command]
+pub async fn playback_stop(app: tauri::AppHandle) {
+    let stopped = app.state::<Mutex<Player>>().lock().unwrap().stop();
+    if let Some(id) = stopped {
+        let _ = app.emit(
+            "playback-position",
+            PlaybackStatus {
+                id,
+                position_ms: 0,
+                duration_ms: 0,
+                playing: false,
+            },
+        );
+    }
+}
+
+/// Progress of `transcribe_file`, emitted as `file-transcription-progress`
+#[derive(Clone, Serialize)]
+struct FileTranscriptionProgress {
+    path: String,
+    stage: &'static str,
+    progress: f32,
+}
+
+fn emit_file_progress(app: &tauri::AppHandle, path: &str, stage: &'static str, progress: f32) {
+    let _ = app.emit(
+        "file-transcription-progress",
+        FileTranscriptionProgress {
+            path: path.to_string(),
+            stage,
+            progress,
+        },
+    );
+}
+
+#[tauri::command]
+pub async fn transcribe_file(
+    app: tauri::AppHandle,
+    path: String,
+    language: String,
+) -> Result<Transcription, String> {
+    let language = LanguageChoice::from_code(&language)
+        .ok_or_else(|| format!("Unsupported language: {language}"))?;
+
+    let (transcriber, detect_languages, vocabulary, decoding) = {
+        let resources = app.state::<Arc<Mutex<AppResources>>>();
+        let res = resources.lock().unwrap();
+        (
+            res.transcriber.clone(),
+            res.detect_languages.clone(),
+            res.vocabulary.clone(),
+            res.decoding,
+        )
+    };
+    let transcriber = transcriber.ok_or_else(|| "No model loaded".to_string())?;
+
+    // Decoding and inference are blocking; keep them off the async runtime
+    tauri::async_runtime::spawn_blocking(move || {
+        eprintln!("[Transcribing file: {path}]");
+
+        // Decoding reports progress per packet; only emit whole percent steps
+        let mut last_percent = -1;
+        let audio = crate::decode::decode_file(Path::new(&path), |progress| {
+            #[allow(clippy::cast_possible_truncation)]
+            let percent = (progress * 100.0) as i32;
+            if percent != last_percent {
+                last_percent = percent;
+                emit_file_progress(&app, &path, "decoding", progress);
+            }
+        })
+        .map_err(|e| format!("Failed to decode audio file: {e}"))?;
+
+        eprintln!("[Decoded {} samples at 16kHz]", audio.len());
+        emit_file_progress(&app, &path, "transcribing", 0.0);
+
+        let progress_app = app.clone();
+        let progress_path = path.clone();
+        #[allow(clippy::cast_precision_loss)]
+        let transcript = transcriber
+            .transcribe_with_progress(
+                &audio,
+                &TranscribeOptions {
+                    language,
+                    detect_among: &detect_languages,
+                    translate: false,
+                    vocabulary: &vocabulary,
+                    decoding,
+                },
+                Some(move |percent: i32| {
+                    let progress = percent as f32 / 100.0;
+                    emit_file_progress(&progress_app, &progress_path, "transcribing", progress);
+                }),
+            )
+            .map_err(|e| format!("Failed to transcribe file: {e}"))?;
+
+        if transcript.text.is_empty() {
+            return Err("No speech detected in file".to_string());
+        }
+
+        let history_db = app.state::<Arc<HistoryDb>>();
+        let record = history_db
+            .save_transcription(
+                &transcript.text,
+                transcript.language.code(),
+                transcript.translated,
+                audio.len(),
+            )
+            .map_err(|e| format!("Failed to save transcription: {e}"))?;
+
+        eprintln!("[Saved file transcription to history: id={}]", record.id);
+        emit_file_progress(&app, &path, "done", 1.0);
+        let _ = app.emit("transcription-added", &record);
+        Ok(record)
+    })
+    .await
+    .map_err(|e| format!("File transcription task failed: {e}"))?
+}
+
+/// Recordings a crash left behind, found on startup
+#[tauri::command]
+#[allow(clippy::needless_pass_by_value)] // Tauri commands require owned parameters
+pub fn list_orphaned_recordings(app: tauri::AppHandle) -> Vec<OrphanedRecording> {
+    app.state::<Arc<SpillStore>>().orphans()
+}
+
+/// Transcribe a recording a crash left behind into history, then delete it.
+#[tauri::command]
+pub async fn recover_orphaned_recording(
+    app: tauri::AppHandle,
+    id: String,
+) -> Result<Transcription, String> {
+    let spill_store = app.state::<Arc<SpillStore>>().inner().clone();
+    let orphan = spill_store
+        .orphan(&id)
+        .ok_or_else(|| format!("No unsaved recording {id}"))?;
+    let language = LanguageChoice::from_code(&orphan.language)
+        .ok_or_else(|| format!("Unsupported language: {}", orphan.language))?;
+
+    let (transcriber, conditioning, detect_languages, vocabulary, decoding, save_audio, quota_mb) = {
+        let resources = app.state::<Arc<Mutex<AppResources>>>();
+        let res = resources.lock().unwrap();
+        (
+            res.transcriber.clone(),
+            res.recorder.conditioning(),
+            res.detect_languages.clone(),
+            res.vocabulary.clone(),
+            res.decoding,
+            res.save_audio,
+            res.audio_quota_mb,
+        )
+    };
+    let transcriber = transcriber.ok_or_else(|| "No model loaded".to_string())?;
+
+    // Inference is blocking; keep it off the async runtime
+    tauri::async_runtime::spawn_blocking(move || {
+        eprintln!("[Recovering recording {id}]");
+        let read = |e| format!("Failed to read recording: {e}");
+        let mic = spill_store.read(&orphan).map_err(read)?;
+        let loopback = spill_store.read_loopback(&orphan).map_err(read)?;
+        // Conditioned and mixed like a live recording; the system audio spill is already aligned
+        let audio = conditioning
+            .apply(Some(mic), loopback.map(|loopback| (loopback, 0)))
+            .map_err(|e| format!("Failed to process recording: {e}"))?;
+
+        let transcript = match vad::trim_silence(&audio) {
+            Some(speech) => Some(
+                transcriber
+                    .transcribe(
+                        speech,
+                        &TranscribeOptions {
+                            language,
+                            detect_among: &detect_languages,
+                            translate: orphan.translate,
+                            vocabulary: &vocabulary,
+                            decoding,
+                        },
+                    )
+                    .map_err(|e| format!("Failed to transcribe recording: {e}"))?,
+            ),
+            None => None,
+        };
+        let Some(transcript) = transcript.filter(|t| !t.text.is_empty()) else {
+            // Nothing worth keeping
+            let _ = spill_store.remove_orphan(&id);
+            return Err("No speech detected in recording".to_string());
+        };
+
+        let history_db = app.state::<Arc<HistoryDb>>();
+        let audio_store = app.state::<Arc<AudioStore>>();
+        let record = store_transcription(
+            &history_db,
+            &audio_store,
+            &transcript.text,
+            transcript.language.code(),
+            transcript.translated,
+            &audio,
+            save_audio.then_some(quota_mb),
+        )
+        .map_err(|e| format!("Failed to save transcription: {e}"))?;
+
+        if let Err(e) = spill_store.remove_orphan(&id) {
+            eprintln!("[Failed to delete spill file: {e}]");
+        }
+        let _ = app.emit("transcription-added", &record);
+        Ok(record)
+    })
+    .await
+    .map_err(|e| format!("Recovery task failed: {e}"))?
+}
+
+/// Delete a recording a crash left behind without transcribing it.
+#[tauri::command]
+pub async fn discard_orphaned_recording(app: tauri::AppHandle, id: String) -> Result<(), String> {
+    app.state::<Arc<SpillStore>>()
+        .remove_orphan(&id)
+        .map_err(|e| format!("Failed to delete recording: {e}"))
+}
+
+/// Terms passed to Whisper so it spells them right
+#[tauri::command]
+#[allow(clippy::needless_pass_by_value)] // Tauri commands require owned parameters
+pub fn get_vocabulary(app: tauri::AppHandle) -> Vocabulary {
+    let resources = app.state::<Arc<Mutex<AppResources>>>();
+    let res = resources.lock().unwrap();
+    res.vocabulary.clone()
+}
+
+/// Add a term for one language, or for every language without one.
+/// Returns the updated vocabulary.
+#[tauri::command]
+#[allow(clippy::needless_pass_by_value)] // Tauri commands require owned parameters
+pub fn add_vocabulary_term(
+    app: tauri::AppHandle,
+    term: String,
+    language: Option<String>,
+) -> Result<Vocabulary, String> {
+    let language = vocabulary_language(language.as_deref())?;
+    update_vocabulary(&app, |vocabulary| vocabulary.add(&term, language)).map(|(_, v)| v)
+}
+
+/// Remove a term from the list of one language, or the global list without one.
+/// Returns the updated vocabulary.
+#[tauri::command]
+#[allow(clippy::needless_pass_by_value)] // Tauri commands require owned parameters
+pub fn remove_vocabulary_term(
+    app: tauri::AppHandle,
+    term: String,
+    language: Option<String>,
+) -> Result<Vocabulary, String> {
+    let language = vocabulary_language(language.as_deref())?;
+    update_vocabulary(&app, |vocabulary| Ok(vocabulary.remove(&term, language))).map(|(_, v)| v)
+}
+
+/// Add the terms of a text file, see `Vocabulary::import()`. Nothing is added
+/// if any term is invalid. Returns how many terms were new.
+#[tauri::command]
+#[allow(clippy::needless_pass_by_value)] // Tauri commands require owned parameters
+pub fn import_vocabulary(
+    app: tauri::AppHandle,
+    path: String,
+    language: Option<String>,
+) -> Result<usize, String> {
+    let language = vocabulary_language(language.as_deref())?;
+    let text = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read vocabulary file: {e}"))?;
+    let (added, _) = update_vocabulary(&app, |vocabulary| vocabulary.import(&text, language))?;
+    eprintln!("[Imported {added} vocabulary terms from {path}]");
+    Ok(added)
+}
+
+/// The language of a vocabulary list; None is the global list
+fn vocabulary_language(code: Option<&str>) -> Result<Option<Language>, String> {
+    code.map(|code| {
+        Language::from_code(code).ok_or_else(|| format!("Unsupported language: {code}"))
+    })
+    .transpose()
+}
+
+/// Apply a change to the vocabulary and save it, keeping the old one if the change fails.
+fn update_vocabulary<T>(
+    app: &tauri::AppHandle,
+    change: impl FnOnce(&mut Vocabulary) -> crate::error::Result<T>,
+) -> Result<(T, Vocabulary), String> {
+    let store = app
+        .store("settings.json")
+        .map_err(|e| format!("Failed to open store: {e}"))?;
+
+    let resources = app.state::<Arc<Mutex<AppResources>>>();
+    let mut res = resources.lock().unwrap();
+    let mut vocabulary = res.vocabulary.clone();
+    let result = change(&mut vocabulary)?;
+
+    store.set("vocabulary", vocabulary.to_value());
+    store
+        .save()
+        .map_err(|e| format!("Failed to save vocabulary: {e}"))?;
+    res.vocabulary = vocabulary.clone();
+    Ok((result, vocabulary))
 }
@@ -11,6 +11,12 @@ pub const WARMUP_MIN_DISPLAY_SECS: u64 = 1;
 pub const WARMUP_EMIT_INTERVAL_MS: u64 = 100;
 pub const WARMUP_EMIT_COUNT: u8 = 5;
 
+/// Recording length limit: the overlay counts down for this many seconds before auto-stop
+pub const RECORDING_COUNTDOWN_SECS: u64 = 10;
+
+/// Device monitor timing
+pub const DEVICE_POLL_INTERVAL_MS: u64 = 2000;
+
 use tauri::{PhysicalPosition, Position, WebviewWindow};
 
 /// Position overlay at bottom center of current monitor.
@@ -0,0 +1,149 @@
+//! Decoding of audio files (WAV, FLAC, MP3, OGG) for transcription.
+
+use std::fs::File;
+use std::path::Path;
+
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+use crate::audio::to_whisper_format;
+use crate::error::{Error, Result};
+
+/// Decode an audio file into 16kHz mono samples ready for Whisper.
+/// `on_progress` receives the decoded fraction (0.0 to 1.0) if the file length is known.
+pub fn decode_file(path: &Path, mut on_progress: impl FnMut(f32)) -> Result<Vec<f32>> {
+    let file = File::open(path)
+        .map_err(|e| Error::Decode(format!("failed to open {}: {e}", path.display())))?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(extension);
+    }
+
+    let probed = symphonia::default::get_probe()
+        .format(
+            &hint,
+            stream,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .map_err(|e| Error::Decode(format!("unsupported audio file: {e}")))?;
+    let mut format = probed.format;
+
+    let track = format
+        .tracks()
+        .iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or_else(|| Error::Decode("no audio track found".to_string()))?;
+    let track_id = track.id;
+    let total_frames = track.codec_params.n_frames;
+    let mut sample_rate = track.codec_params.sample_rate;
+    let mut channels = track.codec_params.channels.map(|c| c.count());
+
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .map_err(|e| Error::Decode(format!("unsupported codec: {e}")))?;
+
+    let mut interleaved = Vec::new();
+    let mut sample_buffer: Option<SampleBuffer<f32>> = None;
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            // End of stream
+            Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                break
+            }
+            Err(SymphoniaError::ResetRequired) => break,
+            Err(e) => return Err(Error::Decode(format!("failed to read packet: {e}"))),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // A corrupt packet is skipped rather than failing the whole file
+            Err(SymphoniaError::DecodeError(e)) => {
+                eprintln!("[Skipping undecodable packet: {e}]");
+                continue;
+            }
+            Err(e) => return Err(Error::Decode(format!("failed to decode: {e}"))),
+        };
+
+        let spec = *decoded.spec();
+        sample_rate = Some(spec.rate);
+        channels = Some(spec.channels.count());
+
+        let buffer = match sample_buffer.as_mut() {
+            Some(buffer) if buffer.capacity() >= decoded.capacity() * spec.channels.count() => {
+                buffer
+            }
+            _ => sample_buffer.insert(SampleBuffer::new(decoded.capacity() as u64, spec)),
+        };
+        buffer.copy_interleaved_ref(decoded);
+        interleaved.extend_from_slice(buffer.samples());
+
+        if let Some(total) = total_frames.filter(|&t| t > 0) {
+            #[allow(clippy::cast_precision_loss)]
+            on_progress(((packet.ts() + packet.dur()) as f32 / total as f32).min(1.0));
+        }
+    }
+
+    let (Some(sample_rate), Some(channels)) = (sample_rate, channels) else {
+        return Err(Error::Decode("file contains no audio".to_string()));
+    };
+    on_progress(1.0);
+
+    to_whisper_format(&interleaved, sample_rate, channels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_signal::sine_wave;
+    use crate::transcribe::WHISPER_SAMPLE_RATE;
+
+    #[test]
+    fn test_decodes_stereo_wav_to_whisper_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("memo.wav");
+
+        // 1s of 44.1kHz stereo with the tone on the left channel only
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 44100,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for sample in sine_wave(440.0, 0.5, 44100, 44100) {
+            writer.write_sample((sample * 32767.0) as i16).unwrap();
+            writer.write_sample(0i16).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let mut last_progress = 0.0;
+        let samples = decode_file(&path, |p| last_progress = p).unwrap();
+
+        assert_eq!(samples.len(), WHISPER_SAMPLE_RATE as usize);
+        assert!((last_progress - 1.0).abs() < f32::EPSILON);
+        // Downmixed to mono: the left-only tone comes through at half amplitude
+        let peak = samples.iter().fold(0.0f32, |acc, s| acc.max(s.abs()));
+        assert!((peak - 0.25).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_rejects_non_audio_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        std::fs::write(&path, "not audio").unwrap();
+
+        assert!(matches!(decode_file(&path, |_| {}), Err(Error::Decode(_))));
+    }
+}
@@ -0,0 +1,99 @@
+//! Input device hot-plug handling.
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::{Emitter, Manager};
+use tauri_plugin_notification::NotificationExt;
+
+use crate::audio::device_exists;
+use crate::constants::DEVICE_POLL_INTERVAL_MS;
+use crate::settings::RecordingState;
+use crate::AppResources;
+
+/// Payload of the `audio-device-changed` event
+#[derive(Clone, Serialize)]
+struct DeviceChanged {
+    device: String,
+    fallback: bool,
+}
+
+/// Spawn a background thread that watches the input device.
+/// Falls back to the default device when the current one disappears and
+/// switches back once the preferred device is available again.
+pub fn spawn_device_monitor(app: &tauri::AppHandle) {
+    let app_handle = app.clone();
+
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_millis(DEVICE_POLL_INTERVAL_MS));
+
+        let preferred = {
+            let resources = app_handle.state::<Arc<Mutex<AppResources>>>();
+            let res = resources.lock().unwrap();
+            res.recorder.preferred_device().map(String::from)
+        };
+
+        // Enumerate devices without holding the lock, this can take a while
+        let preferred_present = preferred.is_none() || device_exists(preferred.as_deref());
+
+        if let Some(change) = check_device(&app_handle, preferred_present) {
+            notify_device_changed(&app_handle, &change);
+        }
+    });
+}
+
+/// Recover or restore the input device if needed.
+/// Returns the new device if a switch happened.
+fn check_device(app: &tauri::AppHandle, preferred_present: bool) -> Option<DeviceChanged> {
+    let resources = app.state::<Arc<Mutex<AppResources>>>();
+    let mut res = resources.lock().unwrap();
+
+    // Never swap the device out from under an active recording
+    if matches!(
+        res.state.get(),
+        RecordingState::Recording | RecordingState::Transcribing
+    ) {
+        return None;
+    }
+
+    let result = if res.recorder.is_using_fallback() && preferred_present {
+        let preferred = res.recorder.preferred_device().map(String::from);
+        eprintln!("[Preferred audio device reconnected]");
+        res.recorder.set_device(preferred.as_deref())
+    } else if res.recorder.has_stream_failed()
+        || (!res.recorder.is_using_fallback() && !preferred_present)
+    {
+        eprintln!("[Audio device lost, falling back to default]");
+        res.recorder.fall_back_to_default()
+    } else {
+        return None;
+    };
+
+    if let Err(e) = result {
+        eprintln!("[Failed to switch audio device: {e}]");
+        return None;
+    }
+
+    Some(DeviceChanged {
+        device: res.recorder.device_name(),
+        fallback: res.recorder.is_using_fallback(),
+    })
+}
+
+fn notify_device_changed(app: &tauri::AppHandle, change: &DeviceChanged) {
+    let _ = app.emit("audio-device-changed", change.clone());
+
+    let body = if change.fallback {
+        format!("Microphone disconnected, using {}", change.device)
+    } else {
+        format!("Using microphone {}", change.device)
+    };
+    let _ = app
+        .notification()
+        .builder()
+        .title("Scribe")
+        .body(body)
+        .show();
+}
@@ -11,6 +11,9 @@ pub enum Error {
     #[error("database error: {0}")]
     Database(String),
 
+    #[error("decode error: {0}")]
+    Decode(String),
+
     #[error("hotkey error: {0}")]
     Hotkey(String),
 
@@ -23,8 +26,14 @@ pub enum Error {
     #[error("resampling error: {0}")]
     Resample(String),
 
+    #[error("storage error: {0}")]
+    Storage(String),
+
     #[error("transcription error: {0}")]
     Transcription(String),
+
+    #[error("vocabulary error: {0}")]
+    Vocabulary(String),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
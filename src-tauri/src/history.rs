@@ -1,5 +1,6 @@
 //! History module for storing and retrieving transcription records.
 
+use std::collections::HashSet;
 use std::sync::Mutex;
 
 use chrono::{DateTime, Utc};
@@ -17,10 +18,14 @@ const MAX_HISTORY_SIZE: i64 = 50;
 pub struct Transcription {
     pub id: i64,
     pub text: String,
+    /// Language that was spoken; the text is English if it was translated
     pub language: String,
+    pub translated: bool,
     pub duration_ms: i64,
     pub word_count: i32,
     pub created_at: String,
+    /// Saved recording for this entry, if audio saving was enabled
+    pub audio_path: Option<String>,
 }
 
 /// Thread-safe wrapper around the database connection
@@ -58,12 +63,18 @@ impl HistoryDb {
                 language TEXT NOT NULL,
                 duration_ms INTEGER NOT NULL,
                 word_count INTEGER NOT NULL,
-                created_at TEXT NOT NULL
+                created_at TEXT NOT NULL,
+                audio_path TEXT,
+                translated INTEGER NOT NULL DEFAULT 0
             )",
             [],
         )
         .map_err(|e| Error::Database(format!("failed to create table: {e}")))?;
 
+        // Columns added after the initial schema
+        add_column_if_missing(&conn, "audio_path", "TEXT")?;
+        add_column_if_missing(&conn, "translated", "INTEGER NOT NULL DEFAULT 0")?;
+
         Ok(Self {
             conn: Mutex::new(conn),
         })
@@ -74,6 +85,7 @@ impl HistoryDb {
         &self,
         text: &str,
         language: &str,
+        translated: bool,
         sample_count: usize,
     ) -> Result<Transcription> {
         let mut conn = self.conn.lock().unwrap();
@@ -99,9 +111,16 @@ impl HistoryDb {
             .map_err(|e| Error::Database(format!("failed to start transaction: {e}")))?;
 
         tx.execute(
-            "INSERT INTO transcriptions (text, language, duration_ms, word_count, created_at)
-             VALUES (?1, ?2, ?3, ?4, ?5)",
-            params![text, language, duration_ms, word_count, created_at_str],
+            "INSERT INTO transcriptions (text, language, translated, duration_ms, word_count, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                text,
+                language,
+                translated,
+                duration_ms,
+                word_count,
+                created_at_str
+            ],
         )
         .map_err(|e| Error::Database(format!("failed to insert transcription: {e}")))?;
 
@@ -123,19 +142,49 @@ impl HistoryDb {
             id,
             text: text.to_string(),
             language: language.to_string(),
+            translated,
             duration_ms,
             word_count,
             created_at: created_at_str,
+            audio_path: None,
         })
     }
 
+    /// Link a saved recording to a transcription, or unlink it with None
+    pub fn set_audio_path(&self, id: i64, audio_path: Option<&str>) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE transcriptions SET audio_path = ?1 WHERE id = ?2",
+            params![audio_path, id],
+        )
+        .map_err(|e| Error::Database(format!("failed to update audio path: {e}")))?;
+        Ok(())
+    }
+
+    /// Ids of all transcriptions still in history
+    pub fn transcription_ids(&self) -> Result<HashSet<i64>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare("SELECT id FROM transcriptions")
+            .map_err(|e| Error::Database(format!("failed to prepare query: {e}")))?;
+
+        let ids = stmt
+            .query_map([], |row| row.get(0))
+            .map_err(|e| Error::Database(format!("failed to query ids: {e}")))?
+            .collect::<std::result::Result<HashSet<_>, _>>()
+            .map_err(|e| Error::Database(format!("failed to collect ids: {e}")))?;
+
+        Ok(ids)
+    }
+
     /// Get the most recent transcriptions
     pub fn get_history(&self, limit: i64) -> Result<Vec<Transcription>> {
         let conn = self.conn.lock().unwrap();
 
         let mut stmt = conn
             .prepare(
-                "SELECT id, text, language, duration_ms, word_count, created_at
+                "SELECT id, text, language, duration_ms, word_count, created_at, audio_path,
+                        translated
                  FROM transcriptions
                  ORDER BY created_at DESC
                  LIMIT ?1",
@@ -151,6 +200,8 @@ impl HistoryDb {
                     duration_ms: row.get(3)?,
                     word_count: row.get(4)?,
                     created_at: row.get(5)?,
+                    audio_path: row.get(6)?,
+                    translated: row.get(7)?,
                 })
             })
             .map_err(|e| Error::Database(format!("failed to query history: {e}")))?
@@ -172,6 +223,23 @@ impl HistoryDb {
     }
 }
 
+/// Add a column to the transcriptions table of databases created by older versions
+fn add_column_if_missing(conn: &Connection, column: &str, definition: &str) -> Result<()> {
+    let exists = conn
+        .prepare("SELECT 1 FROM pragma_table_info('transcriptions') WHERE name = ?1")
+        .and_then(|mut stmt| stmt.exists([column]))
+        .map_err(|e| Error::Database(format!("failed to inspect schema: {e}")))?;
+
+    if !exists {
+        conn.execute(
+            &format!("ALTER TABLE transcriptions ADD COLUMN {column} {definition}"),
+            [],
+        )
+        .map_err(|e| Error::Database(format!("failed to add column {column}: {e}")))?;
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -183,10 +251,13 @@ mod tests {
         let db = HistoryDb::new(temp_dir.path()).unwrap();
 
         // Save a transcription (16000 samples = 1 second at 16kHz)
-        let transcription = db.save_transcription("Hello world", "en", 16000).unwrap();
+        let transcription = db
+            .save_transcription("Hello world", "en", false, 16000)
+            .unwrap();
 
         assert_eq!(transcription.text, "Hello world");
         assert_eq!(transcription.language, "en");
+        assert!(!transcription.translated);
         assert_eq!(transcription.duration_ms, 1000);
         assert_eq!(transcription.word_count, 2);
 
@@ -196,12 +267,24 @@ mod tests {
         assert_eq!(history[0].text, "Hello world");
     }
 
+    #[test]
+    fn test_translation_keeps_source_language() {
+        let temp_dir = tempdir().unwrap();
+        let db = HistoryDb::new(temp_dir.path()).unwrap();
+
+        db.save_transcription("Good morning", "de", true, 16000)
+            .unwrap();
+        let history = db.get_history(10).unwrap();
+        assert_eq!(history[0].language, "de");
+        assert!(history[0].translated);
+    }
+
     #[test]
     fn test_delete_transcription() {
         let temp_dir = tempdir().unwrap();
         let db = HistoryDb::new(temp_dir.path()).unwrap();
 
-        let transcription = db.save_transcription("Test", "de", 8000).unwrap();
+        let transcription = db.save_transcription("Test", "de", false, 8000).unwrap();
         let deleted = db.delete_transcription(transcription.id).unwrap();
         assert!(deleted);
 
@@ -209,6 +292,50 @@ mod tests {
         assert!(history.is_empty());
     }
 
+    #[test]
+    fn test_audio_path_link() {
+        let temp_dir = tempdir().unwrap();
+        let db = HistoryDb::new(temp_dir.path()).unwrap();
+
+        let transcription = db.save_transcription("Hello", "en", false, 16000).unwrap();
+        assert!(transcription.audio_path.is_none());
+
+        db.set_audio_path(transcription.id, Some("/tmp/1.wav"))
+            .unwrap();
+        let history = db.get_history(10).unwrap();
+        assert_eq!(history[0].audio_path.as_deref(), Some("/tmp/1.wav"));
+        assert_eq!(
+            db.transcription_ids().unwrap(),
+            HashSet::from([transcription.id])
+        );
+    }
+
+    #[test]
+    fn test_migrates_old_schema() {
+        let temp_dir = tempdir().unwrap();
+        {
+            let conn = Connection::open(temp_dir.path().join("history.db")).unwrap();
+            conn.execute(
+                "CREATE TABLE transcriptions (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    text TEXT NOT NULL,
+                    language TEXT NOT NULL,
+                    duration_ms INTEGER NOT NULL,
+                    word_count INTEGER NOT NULL,
+                    created_at TEXT NOT NULL
+                )",
+                [],
+            )
+            .unwrap();
+        }
+
+        let db = HistoryDb::new(temp_dir.path()).unwrap();
+        db.save_transcription("Old", "en", false, 16000).unwrap();
+        let history = db.get_history(10).unwrap();
+        assert!(history[0].audio_path.is_none());
+        assert!(!history[0].translated);
+    }
+
     #[test]
     fn test_max_history_cleanup() {
         let temp_dir = tempdir().unwrap();
@@ -216,7 +343,7 @@ mod tests {
 
         // Insert more than MAX_HISTORY_SIZE entries
         for i in 0..55 {
-            db.save_transcription(&format!("Entry {}", i), "en", 16000)
+            db.save_transcription(&format!("Entry {}", i), "en", false, 16000)
                 .unwrap();
         }
 
@@ -1,32 +1,56 @@
 mod audio;
+mod audio_store;
 mod commands;
 mod constants;
+mod decode;
+mod device_monitor;
 mod error;
 mod history;
 mod input;
+mod meter;
+mod mic_test;
+mod playback;
 mod recording;
 mod settings;
 mod shortcuts;
+mod source;
+mod spill;
+#[cfg(test)]
+pub(crate) mod test_signal;
 mod transcribe;
 mod tray;
+mod vad;
+mod vocabulary;
 mod warmup;
 
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 use tauri::Manager;
+use tauri_plugin_notification::NotificationExt;
 use tauri_plugin_store::StoreExt;
 
 use crate::audio::AudioRecorder;
+use crate::audio_store::AudioStore;
 use crate::commands::{
-    delete_transcription, disable_shortcuts, enable_shortcuts, get_history, list_audio_devices,
-    reload_settings, validate_audio_device,
+    add_vocabulary_term, delete_transcription, disable_shortcuts, discard_orphaned_recording,
+    enable_shortcuts, get_history, get_vocabulary, import_vocabulary, list_audio_devices,
+    list_languages, list_loopback_devices, list_orphaned_recordings, list_output_devices,
+    play_mic_test_sample, playback_pause, playback_play, playback_seek, playback_stop,
+    record_mic_test_sample, recover_orphaned_recording, reload_settings, remove_vocabulary_term,
+    start_mic_test, stop_mic_test, transcribe_file, validate_audio_device,
 };
 use crate::history::HistoryDb;
 use crate::input::TextInput;
+use crate::mic_test::MicTest;
+use crate::playback::Player;
 use crate::settings::{AppSettings, AppStateHolder, OutputMode};
 use crate::shortcuts::{setup_mute_shortcut, setup_shortcut};
-use crate::transcribe::{Language, Transcriber};
+use crate::spill::SpillStore;
+use crate::transcribe::{DecodingSettings, Language, LanguageChoice, Transcriber};
 use crate::tray::{create_tray, show_main_window};
+use crate::vocabulary::Vocabulary;
 
 /// Shared app resources
 pub struct AppResources {
@@ -35,18 +59,50 @@ pub struct AppResources {
     pub text_input: TextInput,
     pub state: AppStateHolder,
     /// The language to use for the current/next transcription
-    pub pending_language: Language,
+    pub pending_language: LanguageChoice,
+    /// Whether the current/next transcription is translated to English
+    pub pending_translate: bool,
+    /// Languages automatic detection picks from; empty allows every language
+    pub detect_languages: Vec<Language>,
+    /// Terms passed to Whisper as the initial prompt
+    pub vocabulary: Vocabulary,
+    /// How Whisper decodes
+    pub decoding: DecodingSettings,
     /// Hotkey settings for tray tooltip
-    pub hotkey_en: String,
+    pub hotkey_record: String,
     pub hotkey_mute: String,
     /// Output mode for transcribed text
     pub output_mode: OutputMode,
+    /// Whether recordings are saved next to their history entry, and the storage quota
+    pub save_audio: bool,
+    pub audio_quota_mb: u32,
+    /// Meter readings per second sent to the overlay while recording
+    pub meter_rate_hz: u32,
+    /// Shorter hotkey presses are discarded as accidental taps
+    pub min_press_ms: u32,
+    /// When the current recording started
+    pub recording_started: Option<Instant>,
+    /// Spill file of the current recording, deleted once it is saved to history
+    pub spill_path: Option<PathBuf>,
 }
 
 /// Initialize audio recorder with optional device.
 fn init_audio_recorder(settings: &AppSettings) -> Result<AudioRecorder, String> {
-    AudioRecorder::new(settings.audio_device.as_deref())
-        .map_err(|e| format!("Failed to init audio: {e}"))
+    AudioRecorder::new(
+        settings.audio_device.as_deref(),
+        settings.capture_format,
+        settings.input_gain,
+        settings.preroll_ms,
+        settings.input_channels.clone(),
+        settings.dsp,
+        settings.max_recording_secs,
+    )
+    .map(|mut recorder| {
+        recorder.set_loopback(settings.loopback.clone());
+        recorder.set_trailing_ms(settings.trailing_ms);
+        recorder
+    })
+    .map_err(|e| format!("Failed to init audio: {e}"))
 }
 
 /// Initialize transcriber from model path.
@@ -75,6 +131,51 @@ fn init_history_db(app: &tauri::App) -> Result<Arc<HistoryDb>, String> {
         .map_err(|e| format!("Failed to init history database: {e}"))
 }
 
+/// Initialize storage for saved recordings.
+fn init_audio_store(app: &tauri::App) -> Result<Arc<AudioStore>, String> {
+    let app_data_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data dir: {e}"))?;
+    AudioStore::new(&app_data_dir)
+        .map(Arc::new)
+        .map_err(|e| format!("Failed to init audio storage: {e}"))
+}
+
+/// Initialize the spill directory for recordings in progress.
+fn init_spill_store(app: &tauri::App) -> Result<Arc<SpillStore>, String> {
+    let app_data_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data dir: {e}"))?;
+    SpillStore::new(&app_data_dir)
+        .map(Arc::new)
+        .map_err(|e| format!("Failed to init spill storage: {e}"))
+}
+
+/// Let the user know about recordings a crash left behind; the history view offers to
+/// transcribe them.
+fn notify_orphaned_recordings(app: &tauri::AppHandle, spill_store: &SpillStore) {
+    let count = spill_store.orphans().len();
+    if count == 0 {
+        return;
+    }
+
+    eprintln!("[Found {count} unsaved recording(s) from a previous run]");
+    let body = if count == 1 {
+        "Found an unsaved recording from the last session. Open Scribe to transcribe it."
+            .to_string()
+    } else {
+        format!("Found {count} unsaved recordings from the last session. Open Scribe to transcribe them.")
+    };
+    let _ = app
+        .notification()
+        .builder()
+        .title("Scribe")
+        .body(body)
+        .show();
+}
+
 /// Setup window close handler to hide window instead of quitting.
 fn setup_window_close_handler(window: &tauri::WebviewWindow) {
     let window_clone = window.clone();
@@ -100,15 +201,9 @@ fn setup_window_close_handler(window: &tauri::WebviewWindow) {
 
 /// Register all global shortcuts from settings.
 fn register_shortcuts(app: &tauri::AppHandle, settings: &AppSettings) {
-    if let Err(e) = setup_shortcut(app, &settings.hotkey_en, Language::English) {
-        eprintln!("[Failed to setup English shortcut: {e}]");
-    }
-
-    if let Some(ref hotkey) = settings.hotkey_de {
-        if !hotkey.is_empty() {
-            if let Err(e) = setup_shortcut(app, hotkey, Language::German) {
-                eprintln!("[Failed to setup German shortcut: {e}]");
-            }
+    for hotkey in &settings.language_hotkeys {
+        if let Err(e) = setup_shortcut(app, hotkey) {
+            eprintln!("[Failed to setup {} shortcut: {e}]", hotkey.language.name());
         }
     }
 
@@ -135,8 +230,27 @@ pub fn run() {
             reload_settings,
             get_history,
             delete_transcription,
+            transcribe_file,
             list_audio_devices,
             validate_audio_device,
+            list_output_devices,
+            list_loopback_devices,
+            list_languages,
+            list_orphaned_recordings,
+            recover_orphaned_recording,
+            discard_orphaned_recording,
+            get_vocabulary,
+            add_vocabulary_term,
+            remove_vocabulary_term,
+            import_vocabulary,
+            playback_play,
+            playback_pause,
+            playback_seek,
+            playback_stop,
+            start_mic_test,
+            stop_mic_test,
+            record_mic_test_sample,
+            play_mic_test_sample,
             disable_shortcuts,
             enable_shortcuts
         ])
@@ -149,22 +263,39 @@ pub fn run() {
             let recorder = init_audio_recorder(&settings)?;
             let transcriber = init_transcriber(settings.model_path.as_deref());
             let history_db = init_history_db(app)?;
+            let audio_store = init_audio_store(app)?;
+            let spill_store = init_spill_store(app)?;
+            notify_orphaned_recordings(app.handle(), &spill_store);
 
             // Manage state
             app.manage(history_db);
+            app.manage(audio_store);
+            app.manage(spill_store);
+            app.manage(Mutex::new(Player::new(settings.playback_device.clone())));
+            app.manage(Mutex::new(None::<MicTest>));
             app.manage(Arc::new(Mutex::new(AppResources {
                 recorder,
                 transcriber: transcriber.clone(),
                 text_input: TextInput::new(),
                 state: AppStateHolder::new(),
-                pending_language: Language::English,
-                hotkey_en: settings.hotkey_en.clone(),
+                pending_language: LanguageChoice::Fixed(Language::ENGLISH),
+                pending_translate: false,
+                detect_languages: settings.detect_languages.clone(),
+                vocabulary: settings.vocabulary.clone(),
+                decoding: settings.decoding,
+                hotkey_record: settings.primary_hotkey().to_string(),
                 hotkey_mute: settings.hotkey_mute.clone(),
                 output_mode: settings.output_mode.clone(),
+                save_audio: settings.save_audio,
+                audio_quota_mb: settings.audio_quota_mb,
+                meter_rate_hz: settings.meter_rate_hz,
+                min_press_ms: settings.min_press_ms,
+                recording_started: None,
+                spill_path: None,
             })));
 
             // Setup tray and shortcuts
-            let _tray = create_tray(app.handle(), &settings.hotkey_en)?;
+            let _tray = create_tray(app.handle(), settings.primary_hotkey())?;
             register_shortcuts(app.handle(), &settings);
 
             // Setup main window
@@ -186,6 +317,9 @@ pub fn run() {
                 warmup::spawn_warmup(app.handle(), t);
             }
 
+            // Watch for the input device disappearing or coming back
+            device_monitor::spawn_device_monitor(app.handle());
+
             Ok(())
         })
         .run(tauri::generate_context!())
@@ -0,0 +1,222 @@
+//! Input metering: peak, RMS and a coarse spectrum of the most recent audio.
+
+use std::time::{Duration, Instant};
+
+use realfft::RealFftPlanner;
+use serde::Serialize;
+
+use crate::audio::CLIP_THRESHOLD;
+
+/// Number of mono samples analysed per reading (about 20ms at 48kHz)
+pub const METER_WINDOW_LEN: usize = 1024;
+
+/// Number of spectrum bands, spaced logarithmically between the edges below
+const BAND_COUNT: usize = 8;
+const BAND_MIN_HZ: f32 = 100.0;
+const BAND_MAX_HZ: f32 = 8000.0;
+
+/// Band levels are reported on a dB scale from this floor (0.0) up to full scale (1.0)
+const BAND_FLOOR_DB: f32 = -70.0;
+
+/// Default and allowed range of meter readings per second
+pub const DEFAULT_METER_RATE_HZ: u32 = 20;
+pub const MIN_METER_RATE_HZ: u32 = 5;
+pub const MAX_METER_RATE_HZ: u32 = 60;
+
+/// A window whose samples all lie within this range is flat: digital silence or a
+/// constant offset, as produced by a hardware mute switch. A live microphone's
+/// noise floor always moves more than this (-80 dBFS).
+const FLAT_RANGE: f32 = 1e-4;
+
+/// How long the input has to stay flat before it is reported as muted
+const FLAT_REPORT_AFTER: Duration = Duration::from_secs(2);
+
+/// One meter reading, emitted as `audio-meter`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Metering {
+    /// Highest magnitude in the window (0.0 to 1.0)
+    pub peak: f32,
+    /// RMS of the window (0.0 to 1.0)
+    pub rms: f32,
+    /// RMS scaled by the display gain, for visualization
+    pub level: f32,
+    /// Fraction of samples at or near full scale
+    pub clipping: f32,
+    /// Spectrum from low to high frequencies (0.0 to 1.0 each)
+    pub bands: Vec<f32>,
+    /// Whether the window is flat, see `FLAT_RANGE`
+    pub flat: bool,
+}
+
+impl Metering {
+    /// Analyse a window of mono samples captured at `sample_rate`.
+    pub fn analyze(samples: &[f32], sample_rate: u32, level_gain: f32) -> Self {
+        if samples.is_empty() {
+            return Self {
+                bands: vec![0.0; BAND_COUNT],
+                flat: true,
+                ..Self::default()
+            };
+        }
+
+        let mut peak = 0.0f32;
+        let mut min = f32::MAX;
+        let mut max = f32::MIN;
+        let mut sum_squares = 0.0f32;
+        let mut clipped = 0usize;
+        for &sample in samples {
+            peak = peak.max(sample.abs());
+            min = min.min(sample);
+            max = max.max(sample);
+            sum_squares += sample * sample;
+            if sample.abs() >= CLIP_THRESHOLD {
+                clipped += 1;
+            }
+        }
+
+        #[allow(clippy::cast_precision_loss)]
+        let len = samples.len() as f32;
+        let rms = (sum_squares / len).sqrt();
+        #[allow(clippy::cast_precision_loss)]
+        let clipping = clipped as f32 / len;
+
+        Self {
+            peak,
+            rms,
+            level: rms * level_gain,
+            clipping,
+            bands: spectrum_bands(samples, sample_rate),
+            flat: max - min < FLAT_RANGE,
+        }
+    }
+}
+
+/// Band levels of a Hann-windowed FFT over the samples.
+fn spectrum_bands(samples: &[f32], sample_rate: u32) -> Vec<f32> {
+    let len = samples.len();
+    let mut planner = RealFftPlanner::<f32>::new();
+    let fft = planner.plan_fft_forward(len);
+
+    #[allow(clippy::cast_precision_loss)]
+    let mut input: Vec<f32> = samples
+        .iter()
+        .enumerate()
+        .map(|(i, &s)| {
+            let w = 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / len as f32).cos();
+            s * w
+        })
+        .collect();
+    let mut spectrum = fft.make_output_vec();
+    if fft.process(&mut input, &mut spectrum).is_err() {
+        return vec![0.0; BAND_COUNT];
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    let bin_hz = sample_rate as f32 / len as f32;
+    #[allow(clippy::cast_precision_loss)]
+    let nyquist = sample_rate as f32 / 2.0;
+    let max_hz = BAND_MAX_HZ.min(nyquist);
+    #[allow(clippy::cast_precision_loss)]
+    let ratio = (max_hz / BAND_MIN_HZ).powf(1.0 / BAND_COUNT as f32);
+    // A full-scale sine peaks at len / 4 after the Hann window
+    #[allow(clippy::cast_precision_loss)]
+    let full_scale = len as f32 / 4.0;
+
+    (0..BAND_COUNT)
+        .map(|band| {
+            #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+            let low = BAND_MIN_HZ * ratio.powi(band as i32);
+            let high = low * ratio;
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let (first, last) = ((low / bin_hz) as usize, (high / bin_hz) as usize);
+            let magnitude = spectrum[first..=last.min(spectrum.len() - 1)]
+                .iter()
+                .map(|bin| bin.norm())
+                .fold(0.0f32, f32::max);
+
+            let db = 20.0 * (magnitude / full_scale).max(1e-9).log10();
+            ((db - BAND_FLOOR_DB) / -BAND_FLOOR_DB).clamp(0.0, 1.0)
+        })
+        .collect()
+}
+
+/// Tracks how long the input has been flat during a recording
+#[derive(Default)]
+pub struct FlatInputDetector {
+    flat_since: Option<Instant>,
+    reported: bool,
+}
+
+impl FlatInputDetector {
+    /// Feed a reading. Returns true once per flat stretch, when it has lasted
+    /// long enough to suggest the microphone is muted in hardware.
+    pub fn update(&mut self, metering: &Metering, now: Instant) -> bool {
+        if !metering.flat {
+            self.flat_since = None;
+            self.reported = false;
+            return false;
+        }
+
+        let since = *self.flat_since.get_or_insert(now);
+        if !self.reported && now.duration_since(since) >= FLAT_REPORT_AFTER {
+            self.reported = true;
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_signal::sine_wave;
+
+    #[test]
+    fn test_levels_of_sine() {
+        let metering =
+            Metering::analyze(&sine_wave(1000.0, 0.5, 48000, METER_WINDOW_LEN), 48000, 2.0);
+        assert!((metering.peak - 0.5).abs() < 0.01);
+        assert!((metering.rms - 0.5 / 2f32.sqrt()).abs() < 0.01);
+        assert!((metering.level - metering.rms * 2.0).abs() < 1e-6);
+        assert!(metering.clipping < f32::EPSILON);
+        assert!(!metering.flat);
+    }
+
+    #[test]
+    fn test_tone_lands_in_its_band() {
+        let metering =
+            Metering::analyze(&sine_wave(1000.0, 0.5, 48000, METER_WINDOW_LEN), 48000, 1.0);
+        let loudest = metering
+            .bands
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(i, _)| i)
+            .unwrap();
+
+        // 1kHz lies in the band spanning roughly 750Hz to 1.3kHz
+        let ratio = (BAND_MAX_HZ / BAND_MIN_HZ).powf(1.0 / BAND_COUNT as f32);
+        let expected = ((1000.0 / BAND_MIN_HZ).ln() / ratio.ln()) as usize;
+        assert_eq!(loudest, expected);
+        // About -6 dBFS
+        assert!(metering.bands[loudest] > 0.85);
+    }
+
+    #[test]
+    fn test_muted_input_is_flat() {
+        // A constant offset is as flat as digital silence
+        let metering = Metering::analyze(&[0.01; METER_WINDOW_LEN], 48000, 1.0);
+        assert!(metering.flat);
+        assert!(metering.bands[BAND_COUNT - 1] < 0.1);
+
+        let mut detector = FlatInputDetector::default();
+        let start = Instant::now();
+        assert!(!detector.update(&metering, start));
+        assert!(detector.update(&metering, start + FLAT_REPORT_AFTER));
+        // Reported once per flat stretch
+        assert!(!detector.update(&metering, start + FLAT_REPORT_AFTER * 2));
+
+        let live = Metering::analyze(&sine_wave(440.0, 0.01, 48000, METER_WINDOW_LEN), 48000, 1.0);
+        assert!(!detector.update(&live, start + FLAT_REPORT_AFTER * 3));
+    }
+}
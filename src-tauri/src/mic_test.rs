@@ -0,0 +1,248 @@
+//! Microphone test for the settings page: live levels and a short sample recording,
+//! on a stream of its own so the recorder and `RecordingState` are left alone.
+
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::Duration;
+
+use cpal::traits::{DeviceTrait, StreamTrait};
+use cpal::{FromSample, Sample, Stream, StreamConfig};
+use rtrb::{Consumer, Producer, RingBuffer};
+use serde::Serialize;
+use tauri::Emitter;
+
+use crate::audio::{
+    find_device, get_device_name, select_input_config, to_whisper_format, CaptureFormat,
+    CLIP_THRESHOLD,
+};
+use crate::error::{Error, Result};
+use crate::source::dispatch_sample_format;
+
+/// How often levels are reported to the frontend
+const LEVEL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Length of the test sample
+pub const SAMPLE_SECS: u32 = 5;
+
+/// Playback id of the test sample; transcription ids are always positive
+pub const SAMPLE_PLAYBACK_ID: i64 = -1;
+
+/// Payload of the `mic-test-level` event
+#[derive(Clone, Serialize)]
+pub struct MicTestLevel {
+    /// RMS of the most recent buffer (0.0 to 1.0)
+    pub rms: f32,
+    /// Highest magnitude since the previous report (0.0 to 1.0)
+    pub peak: f32,
+    pub clipping: bool,
+    /// Fraction of the test sample recorded so far, while one is being recorded
+    pub sample_progress: Option<f32>,
+}
+
+/// State shared with the input callback
+struct MicTestShared {
+    rms: AtomicU32,
+    // Non-negative floats order like their bit patterns, so `fetch_max` works on the bits
+    peak: AtomicU32,
+    clipped: AtomicBool,
+    // Whether the callback forwards audio for the test sample
+    capturing: AtomicBool,
+    // Bumped for every test sample started, so the reporter starts it from scratch
+    take: AtomicU32,
+    // The finished test sample, 16kHz mono
+    sample: Mutex<Option<Vec<f32>>>,
+}
+
+/// A running microphone test
+pub struct MicTest {
+    device_name: String,
+    shared: Arc<MicTestShared>,
+    _stream: Stream,
+}
+
+impl MicTest {
+    /// Open the input device with the capture format used for recording,
+    /// and start reporting `mic-test-level` events.
+    pub fn start(
+        app: &tauri::AppHandle,
+        device_id: Option<&str>,
+        format: CaptureFormat,
+    ) -> Result<Self> {
+        let (device, _) = find_device(device_id)?;
+        let device_name = get_device_name(&device);
+        let (stream_config, sample_format) = select_input_config(&device, format)?;
+        let sample_rate = stream_config.sample_rate;
+        let channels = usize::from(stream_config.channels);
+
+        let shared = Arc::new(MicTestShared {
+            rms: AtomicU32::new(0),
+            peak: AtomicU32::new(0),
+            clipped: AtomicBool::new(false),
+            capturing: AtomicBool::new(false),
+            take: AtomicU32::new(0),
+            sample: Mutex::new(None),
+        });
+
+        let capacity = sample_rate as usize * channels * SAMPLE_SECS as usize;
+        let (producer, consumer) = RingBuffer::new(capacity);
+        let stream = dispatch_sample_format!(
+            sample_format,
+            build_stream(&device, &stream_config, producer, &shared)
+        )?;
+        stream
+            .play()
+            .map_err(|e| Error::Audio(format!("failed to start stream: {e}")))?;
+
+        spawn_level_reporter(
+            app,
+            Arc::downgrade(&shared),
+            consumer,
+            sample_rate,
+            channels,
+        );
+        eprintln!("[Microphone test started on {device_name}]");
+
+        Ok(Self {
+            device_name,
+            shared,
+            _stream: stream,
+        })
+    }
+
+    pub fn device_name(&self) -> &str {
+        &self.device_name
+    }
+
+    /// Start recording a new test sample, discarding the previous one
+    /// and any sample still in progress.
+    pub fn record_sample(&self) {
+        *self.shared.sample.lock().unwrap() = None;
+        self.shared.take.fetch_add(1, Ordering::SeqCst);
+        self.shared.capturing.store(true, Ordering::SeqCst);
+    }
+
+    /// The last finished test sample, 16kHz mono
+    pub fn sample(&self) -> Option<Vec<f32>> {
+        self.shared.sample.lock().unwrap().clone()
+    }
+}
+
+impl Drop for MicTest {
+    fn drop(&mut self) {
+        eprintln!("[Microphone test stopped]");
+    }
+}
+
+/// Emit levels until the test is dropped, and assemble the test sample when one is recorded.
+fn spawn_level_reporter(
+    app: &tauri::AppHandle,
+    shared: Weak<MicTestShared>,
+    mut consumer: Consumer<f32>,
+    sample_rate: u32,
+    channels: usize,
+) {
+    let app_handle = app.clone();
+    let sample_len = sample_rate as usize * channels * SAMPLE_SECS as usize;
+    thread::spawn(move || {
+        let mut recorded = Vec::with_capacity(sample_len);
+        let mut current_take = 0;
+        while let Some(shared) = shared.upgrade() {
+            // Audio queued before a take started, or after it finished, isn't part of it
+            let take = shared.take.load(Ordering::SeqCst);
+            let in_take = take == current_take && shared.capturing.load(Ordering::SeqCst);
+            if let Ok(chunk) = consumer.read_chunk(consumer.slots()) {
+                if in_take {
+                    recorded.extend(chunk);
+                } else {
+                    chunk.commit_all();
+                }
+            }
+            if take != current_take {
+                current_take = take;
+                recorded.clear();
+            }
+
+            let mut sample_progress = None;
+            if shared.capturing.load(Ordering::SeqCst) {
+                #[allow(clippy::cast_precision_loss)]
+                let progress = recorded.len() as f32 / sample_len as f32;
+                sample_progress = Some(progress.min(1.0));
+
+                if recorded.len() >= sample_len {
+                    shared.capturing.store(false, Ordering::SeqCst);
+                    recorded.truncate(sample_len);
+                    match to_whisper_format(&recorded, sample_rate, channels) {
+                        Ok(sample) => {
+                            *shared.sample.lock().unwrap() = Some(sample);
+                            let _ = app_handle.emit("mic-test-sample-ready", SAMPLE_SECS);
+                        }
+                        Err(e) => eprintln!("[Failed to convert test sample: {e}]"),
+                    }
+                    recorded.clear();
+                }
+            }
+
+            let level = MicTestLevel {
+                rms: f32::from_bits(shared.rms.load(Ordering::Relaxed)),
+                peak: f32::from_bits(shared.peak.swap(0, Ordering::Relaxed)),
+                clipping: shared.clipped.swap(false, Ordering::Relaxed),
+                sample_progress,
+            };
+            drop(shared);
+
+            let _ = app_handle.emit("mic-test-level", level);
+            thread::sleep(LEVEL_INTERVAL);
+        }
+    });
+}
+
+fn build_stream<T>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    mut producer: Producer<f32>,
+    shared: &Arc<MicTestShared>,
+) -> Result<Stream>
+where
+    T: cpal::Sample + cpal::SizedSample,
+    f32: FromSample<T>,
+{
+    let shared = shared.clone();
+    let channels = usize::from(config.channels);
+    device
+        .build_input_stream(
+            config,
+            move |data: &[T], _: &cpal::InputCallbackInfo| {
+                if data.is_empty() {
+                    return;
+                }
+
+                let mut sum_squares = 0.0f32;
+                let mut peak = 0.0f32;
+                for &sample in data {
+                    let value = f32::from_sample(sample);
+                    sum_squares += value * value;
+                    peak = peak.max(value.abs());
+                }
+
+                #[allow(clippy::cast_precision_loss)]
+                let rms = (sum_squares / data.len() as f32).sqrt();
+                shared.rms.store(rms.to_bits(), Ordering::Relaxed);
+                shared.peak.fetch_max(peak.to_bits(), Ordering::Relaxed);
+                if peak >= CLIP_THRESHOLD {
+                    shared.clipped.store(true, Ordering::Relaxed);
+                }
+
+                // Only whole frames are written so the sample stays channel-aligned
+                if shared.capturing.load(Ordering::SeqCst) {
+                    let writable = data.len().min(producer.slots()) / channels * channels;
+                    if let Ok(chunk) = producer.write_chunk_uninit(writable) {
+                        chunk.fill_from_iter(data.iter().map(|&sample| f32::from_sample(sample)));
+                    }
+                }
+            },
+            |err| eprintln!("mic test stream error: {err}"),
+            None,
+        )
+        .map_err(|e| Error::Audio(format!("failed to build input stream: {e}")))
+}
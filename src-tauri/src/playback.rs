@@ -0,0 +1,252 @@
+//! Playback of saved recordings through a cpal output stream.
+
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::thread;
+use std::time::Duration;
+
+use cpal::traits::{DeviceTrait, StreamTrait};
+use cpal::{FromSample, Stream, StreamConfig};
+use serde::Serialize;
+use tauri::Emitter;
+
+use crate::audio::{find_output_device, resample, to_whisper_format};
+use crate::audio_store::read_wav;
+use crate::error::{Error, Result};
+use crate::source::dispatch_sample_format;
+use crate::transcribe::WHISPER_SAMPLE_RATE;
+
+/// How often the playback position is reported to the frontend
+const POSITION_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Payload of the `playback-position` event
+#[derive(Clone, Serialize)]
+pub struct PlaybackStatus {
+    pub id: i64,
+    pub position_ms: u64,
+    pub duration_ms: u64,
+    pub playing: bool,
+}
+
+/// State shared with the output callback
+struct PlaybackShared {
+    // Mono samples at the output device rate
+    samples: Vec<f32>,
+    // Next frame to play
+    position: AtomicUsize,
+    playing: AtomicBool,
+}
+
+/// A loaded recording and the stream playing it
+struct Playback {
+    id: i64,
+    sample_rate: u32,
+    shared: Arc<PlaybackShared>,
+    _stream: Stream,
+}
+
+impl Playback {
+    fn status(&self) -> PlaybackStatus {
+        status(self.id, self.sample_rate, &self.shared)
+    }
+}
+
+/// Plays one saved recording at a time
+pub struct Player {
+    device_id: Option<String>,
+    current: Option<Playback>,
+}
+
+impl Player {
+    pub fn new(device_id: Option<String>) -> Self {
+        Self {
+            device_id,
+            current: None,
+        }
+    }
+
+    /// Change the output device. Takes effect from the next recording loaded.
+    pub fn set_device(&mut self, device_id: Option<String>) {
+        self.device_id = device_id;
+    }
+
+    /// Id of the loaded recording, if any
+    pub fn current_id(&self) -> Option<i64> {
+        self.current.as_ref().map(|p| p.id)
+    }
+
+    /// Play the recording of a transcription, resuming it if it is already loaded.
+    pub fn play(&mut self, app: &tauri::AppHandle, id: i64, path: &Path) -> Result<()> {
+        if let Some(playback) = self.current.as_ref().filter(|p| p.id == id) {
+            let shared = &playback.shared;
+            // Start over if the previous run played to the end
+            if shared.position.load(Ordering::Relaxed) >= shared.samples.len() {
+                shared.position.store(0, Ordering::Relaxed);
+            }
+            shared.playing.store(true, Ordering::Relaxed);
+            return Ok(());
+        }
+
+        let samples = load_recording(path)?;
+        self.play_samples(app, id, &samples)
+    }
+
+    /// Play 16kHz mono audio from memory, replacing whatever was loaded.
+    pub fn play_samples(&mut self, app: &tauri::AppHandle, id: i64, samples: &[f32]) -> Result<()> {
+        self.stop();
+
+        let device = find_output_device(self.device_id.as_deref())?;
+        let config = device
+            .default_output_config()
+            .map_err(|e| Error::Audio(format!("failed to get default output config: {e}")))?;
+        let sample_rate = config.sample_rate();
+        let sample_format = config.sample_format();
+        let stream_config: StreamConfig = config.into();
+
+        let shared = Arc::new(PlaybackShared {
+            samples: if sample_rate == WHISPER_SAMPLE_RATE {
+                samples.to_vec()
+            } else {
+                resample(samples, WHISPER_SAMPLE_RATE, sample_rate)?
+            },
+            position: AtomicUsize::new(0),
+            playing: AtomicBool::new(true),
+        });
+
+        let stream = dispatch_sample_format!(
+            sample_format,
+            build_output_stream(&device, &stream_config, &shared)
+        )?;
+        stream
+            .play()
+            .map_err(|e| Error::Audio(format!("failed to start playback: {e}")))?;
+
+        spawn_position_reporter(app, id, sample_rate, Arc::downgrade(&shared));
+        eprintln!("[Playing audio {id}]");
+
+        self.current = Some(Playback {
+            id,
+            sample_rate,
+            shared,
+            _stream: stream,
+        });
+        Ok(())
+    }
+
+    /// Pause playback, keeping the position.
+    pub fn pause(&mut self) -> Option<PlaybackStatus> {
+        let playback = self.current.as_ref()?;
+        playback.shared.playing.store(false, Ordering::Relaxed);
+        Some(playback.status())
+    }
+
+    /// Jump to a position in the loaded recording.
+    pub fn seek(&mut self, position_ms: u64) -> Option<PlaybackStatus> {
+        let playback = self.current.as_ref()?;
+        let frame = position_ms * u64::from(playback.sample_rate) / 1000;
+        let frame = usize::try_from(frame)
+            .unwrap_or(usize::MAX)
+            .min(playback.shared.samples.len());
+        playback.shared.position.store(frame, Ordering::Relaxed);
+        Some(playback.status())
+    }
+
+    /// Stop playback and release the output device.
+    /// Returns the id of the recording that was loaded.
+    pub fn stop(&mut self) -> Option<i64> {
+        let playback = self.current.take()?;
+        eprintln!("[Stopped playback of audio {}]", playback.id);
+        Some(playback.id)
+    }
+}
+
+/// Read a saved recording and convert it to 16kHz mono.
+fn load_recording(path: &Path) -> Result<Vec<f32>> {
+    let (interleaved, spec) = read_wav(path)?;
+    to_whisper_format(&interleaved, spec.sample_rate, usize::from(spec.channels))
+}
+
+fn status(id: i64, sample_rate: u32, shared: &PlaybackShared) -> PlaybackStatus {
+    let to_ms = |frames: usize| frames as u64 * 1000 / u64::from(sample_rate);
+    PlaybackStatus {
+        id,
+        position_ms: to_ms(shared.position.load(Ordering::Relaxed)),
+        duration_ms: to_ms(shared.samples.len()),
+        playing: shared.playing.load(Ordering::Relaxed),
+    }
+}
+
+/// Emit `playback-position` while playing, and once on every pause or end,
+/// until the recording is stopped or replaced.
+fn spawn_position_reporter(
+    app: &tauri::AppHandle,
+    id: i64,
+    sample_rate: u32,
+    shared: Weak<PlaybackShared>,
+) {
+    let app_handle = app.clone();
+    thread::spawn(move || {
+        let mut was_playing = false;
+        while let Some(shared) = shared.upgrade() {
+            let status = status(id, sample_rate, &shared);
+            drop(shared);
+
+            if status.playing || was_playing {
+                was_playing = status.playing;
+                let _ = app_handle.emit("playback-position", status);
+            }
+            thread::sleep(POSITION_INTERVAL);
+        }
+    });
+}
+
+/// Build an output stream that plays the shared mono samples on every channel.
+fn build_output_stream<T>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    shared: &Arc<PlaybackShared>,
+) -> Result<Stream>
+where
+    T: cpal::Sample + cpal::SizedSample + FromSample<f32>,
+{
+    let shared = shared.clone();
+    let channels = usize::from(config.channels);
+    device
+        .build_output_stream(
+            config,
+            move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+                let start = shared.position.load(Ordering::Relaxed);
+                let mut position = start;
+                let playing = shared.playing.load(Ordering::Relaxed);
+
+                for frame in data.chunks_mut(channels) {
+                    let sample = if playing {
+                        shared.samples.get(position).copied()
+                    } else {
+                        None
+                    };
+                    if sample.is_some() {
+                        position += 1;
+                    }
+                    frame.fill(T::from_sample(sample.unwrap_or(0.0)));
+                }
+
+                if playing {
+                    // Don't overwrite a seek that happened while this buffer was filled
+                    let _ = shared.position.compare_exchange(
+                        start,
+                        position,
+                        Ordering::Relaxed,
+                        Ordering::Relaxed,
+                    );
+                    if position >= shared.samples.len() {
+                        shared.playing.store(false, Ordering::Relaxed);
+                    }
+                }
+            },
+            |err| eprintln!("playback stream error: {err}"),
+            None,
+        )
+        .map_err(|e| Error::Audio(format!("failed to build output stream: {e}")))
+}
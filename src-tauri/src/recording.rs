@@ -1,27 +1,37 @@
 //! Recording and transcription handling.
 
+use std::path::Path;
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 
 use tauri::{Emitter, Manager};
 use tauri_plugin_notification::NotificationExt;
 
-use crate::constants::{position_overlay_bottom_center, OVERLAY_HEIGHT_RECORDING};
-use crate::history::HistoryDb;
+use crate::audio::CaptureMode;
+use crate::audio_store::AudioStore;
+use crate::constants::{
+    position_overlay_bottom_center, OVERLAY_HEIGHT_RECORDING, RECORDING_COUNTDOWN_SECS,
+};
+use crate::error::{Error, Result};
+use crate::history::{HistoryDb, Transcription};
+use crate::meter::FlatInputDetector;
 use crate::settings::{OutputMode, RecordingState};
-use crate::transcribe::Language;
+use crate::spill::{self, SpillStore};
+use crate::transcribe::{LanguageChoice, TranscribeOptions, Transcript};
 use crate::tray::{show_main_window, update_tray_state, TRAY_ID};
+use crate::vad;
 use crate::AppResources;
 
 /// Update tray icon to reflect the given state.
 fn set_tray_state(
     app: &tauri::AppHandle,
     state: RecordingState,
-    hotkey_en: &str,
+    hotkey_record: &str,
     hotkey_mute: &str,
 ) {
     if let Some(tray) = app.tray_by_id(TRAY_ID) {
-        let _ = update_tray_state(&tray, state, hotkey_en, hotkey_mute);
+        let _ = update_tray_state(&tray, state, hotkey_record, hotkey_mute);
     }
 }
 
@@ -31,7 +41,7 @@ pub fn handle_mute_toggle(app: &tauri::AppHandle) {
     let mut res = resources.lock().unwrap();
 
     // Extract hotkeys while we have the lock
-    let hotkey_en = res.hotkey_en.clone();
+    let hotkey_record = res.hotkey_record.clone();
     let hotkey_mute = res.hotkey_mute.clone();
 
     if res.recorder.is_muted() {
@@ -45,7 +55,7 @@ pub fn handle_mute_toggle(app: &tauri::AppHandle) {
 
         // Update tray icon
         if let Some(tray) = app.tray_by_id(TRAY_ID) {
-            let _ = update_tray_state(&tray, RecordingState::Idle, &hotkey_en, &hotkey_mute);
+            let _ = update_tray_state(&tray, RecordingState::Idle, &hotkey_record, &hotkey_mute);
         }
 
         // Show notification
@@ -63,7 +73,7 @@ pub fn handle_mute_toggle(app: &tauri::AppHandle) {
 
         // Update tray icon
         if let Some(tray) = app.tray_by_id(TRAY_ID) {
-            let _ = update_tray_state(&tray, RecordingState::Muted, &hotkey_en, &hotkey_mute);
+            let _ = update_tray_state(&tray, RecordingState::Muted, &hotkey_record, &hotkey_mute);
         }
 
         // Show notification
@@ -76,66 +86,151 @@ pub fn handle_mute_toggle(app: &tauri::AppHandle) {
     }
 }
 
-/// Start recording audio for the given language.
-pub fn handle_recording_start(app: &tauri::AppHandle, language: Language) {
+/// Why a recording didn't start
+#[derive(Debug)]
+pub enum StartError {
+    /// The model is still warming up
+    WarmingUp,
+    /// The microphone is muted and the capture mode needs it
+    Muted,
+    /// The recorder couldn't start, e.g. without a loopback device
+    Capture(Error),
+}
+
+/// What releasing the hotkey did to the recording
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopOutcome {
+    NotRecording,
+    /// The press was shorter than `min_press_ms`; the recording is thrown away
+    Discarded,
+    /// The recording is to be transcribed by `finish_recording()`
+    Transcribe,
+}
+
+/// What became of a recording once it was transcribed
+#[derive(Debug)]
+pub enum RecordingOutcome {
+    /// The recorder failed; the spill file is left for recovery on the next start
+    StopFailed(Error),
+    /// Nothing was captured, or nothing but silence
+    NoSpeech,
+    /// No model is loaded; the spill file is left for recovery once one is
+    NoModel,
+    /// Whisper failed; the spill file is left for recovery on the next start
+    TranscriptionFailed(Error),
+    /// `record` is the history entry, or None if saving it failed
+    Transcribed {
+        transcript: Transcript,
+        record: Option<Transcription>,
+    },
+}
+
+/// Start recording for the given language and capture mode, spilling to `spill_store`.
+/// This and `stop_recording()`/`finish_recording()` leave the UI alone, so the whole
+/// flow runs without an app.
+pub fn start_recording(
+    res: &mut AppResources,
+    spill_store: &SpillStore,
+    language: LanguageChoice,
+    translate: bool,
+    capture: CaptureMode,
+) -> std::result::Result<(), StartError> {
+    if res.state.get() == RecordingState::WarmingUp {
+        return Err(StartError::WarmingUp);
+    }
+    // System audio alone can still be recorded while muted
+    if res.recorder.is_muted() && capture.uses_microphone() {
+        return Err(StartError::Muted);
+    }
+
+    // Spill the recording to disk as it grows, so a crash doesn't lose it
+    let spill = match spill_store.create(language.code(), translate) {
+        Ok(spill) => Some(spill),
+        Err(e) => {
+            eprintln!("[Recording without a spill file: {e}]");
+            None
+        }
+    };
+    let spill_path = spill.as_ref().map(|spill| spill.path().to_path_buf());
+
+    if let Err(e) = res.recorder.start(capture, spill) {
+        delete_spill(spill_path.as_deref());
+        return Err(StartError::Capture(e));
+    }
+
+    // Store the language to use for transcription
+    res.pending_language = language;
+    res.pending_translate = translate;
+    res.spill_path = spill_path;
+    res.state.set(RecordingState::Recording);
+    res.recording_started = Some(Instant::now());
+    Ok(())
+}
+
+/// Start recording audio for the given language and capture mode,
+/// to be translated to English if `translate` is set.
+pub fn handle_recording_start(
+    app: &tauri::AppHandle,
+    language: LanguageChoice,
+    translate: bool,
+    capture: CaptureMode,
+) {
     let resources = app.state::<Arc<Mutex<AppResources>>>();
     let mut res = resources.lock().unwrap();
 
     // Extract hotkeys early since we need them for early return notifications
-    let hotkey_en = res.hotkey_en.clone();
+    let hotkey_record = res.hotkey_record.clone();
     let hotkey_mute = res.hotkey_mute.clone();
 
-    // Check if warming up
-    if res.state.get() == RecordingState::WarmingUp {
-        eprintln!("[Cannot record - model is still warming up]");
+    // Check if transcriber is loaded
+    if res.transcriber.is_none() {
+        eprintln!("[No model loaded - opening main window]");
         drop(res);
-        let _ = app
-            .notification()
-            .builder()
-            .title("Scribe")
-            .body("Model is starting up, please wait...")
-            .show();
+        show_main_window(app);
         return;
     }
 
-    // Check if muted
-    if res.recorder.is_muted() {
-        eprintln!("[Cannot record - microphone is muted]");
+    let spill_store = app.state::<Arc<SpillStore>>();
+    if let Err(e) = start_recording(&mut res, &spill_store, language, translate, capture) {
         drop(res);
+        let body = match e {
+            StartError::WarmingUp => {
+                eprintln!("[Cannot record - model is still warming up]");
+                "Model is starting up, please wait...".to_string()
+            }
+            StartError::Muted => {
+                eprintln!("[Cannot record - microphone is muted]");
+                format!("Microphone is muted. Press {hotkey_mute} to unmute.")
+            }
+            StartError::Capture(e) => {
+                eprintln!("[Failed to start recording: {e}]");
+                format!("Could not record system audio: {e}")
+            }
+        };
         let _ = app
             .notification()
             .builder()
             .title("Scribe")
-            .body(format!(
-                "Microphone is muted. Press {hotkey_mute} to unmute."
-            ))
+            .body(body)
             .show();
         return;
     }
 
-    // Check if transcriber is loaded
-    if res.transcriber.is_none() {
-        eprintln!("[No model loaded - opening main window]");
-        drop(res);
-        show_main_window(app);
-        return;
-    }
-
-    // Store the language to use for transcription
-    res.pending_language = language;
-
-    // Update state to Recording
-    res.state.set(RecordingState::Recording);
-
-    // Start audio recorder
-    res.recorder.start();
+    let started = res.recording_started.unwrap_or_else(Instant::now);
+    let max_duration = Duration::from_secs(u64::from(res.recorder.max_recording_secs()));
+    let meter_interval = Duration::from_millis(1000 / u64::from(res.meter_rate_hz.max(1)));
 
     // Release lock before tray/overlay operations
     drop(res);
 
     // Update tray icon
     if let Some(tray) = app.tray_by_id(TRAY_ID) {
-        let _ = update_tray_state(&tray, RecordingState::Recording, &hotkey_en, &hotkey_mute);
+        let _ = update_tray_state(
+            &tray,
+            RecordingState::Recording,
+            &hotkey_record,
+            &hotkey_mute,
+        );
     }
 
     // Show overlay window
@@ -148,55 +243,77 @@ pub fn handle_recording_start(app: &tauri::AppHandle, language: Language) {
     // Set overlay to waveform mode
     let _ = app.emit("overlay-mode", "waveform");
 
-    // Spawn thread to emit audio levels
+    // Spawn thread to emit meter readings and enforce the maximum recording length
     let app_clone = app.clone();
+    let mut flat_input = FlatInputDetector::default();
     thread::spawn(move || loop {
         let resources = app_clone.state::<Arc<Mutex<AppResources>>>();
         let res = resources.lock().unwrap();
         let is_recording = res.state.get() == RecordingState::Recording;
-        let level = res.recorder.get_audio_level();
+        let metering = res.recorder.metering();
+        let limit_reached = res.recorder.is_limit_reached();
         drop(res);
 
         if !is_recording {
             break;
         }
 
-        let _ = app_clone.emit("audio-level", level);
-        thread::sleep(std::time::Duration::from_millis(50));
+        let remaining = max_duration.saturating_sub(started.elapsed());
+        if limit_reached || remaining.is_zero() {
+            eprintln!("[Maximum recording length reached, stopping]");
+            let _ = app_clone
+                .notification()
+                .builder()
+                .title("Scribe")
+                .body("Maximum recording length reached")
+                .show();
+            handle_recording_stop(&app_clone);
+            break;
+        }
+
+        if remaining <= Duration::from_secs(RECORDING_COUNTDOWN_SECS) {
+            // Whole seconds left, rounded up so the countdown ends at 1
+            let secs = remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0);
+            let _ = app_clone.emit("recording-countdown", secs);
+        }
+
+        // A perfectly flat input usually means a hardware mute switch is on.
+        // System audio alone is silent whenever nothing plays, so it isn't checked.
+        if capture.uses_microphone() && flat_input.update(&metering, Instant::now()) {
+            let device_name = resources.lock().unwrap().recorder.device_name();
+            eprintln!("[No signal from {device_name}, input may be muted in hardware]");
+            let _ = app_clone.emit("audio-input-flat", &device_name);
+            let _ = app_clone
+                .notification()
+                .builder()
+                .title("Scribe")
+                .body(format!(
+                    "No signal from {device_name}. Check the microphone's mute switch."
+                ))
+                .show();
+        }
+
+        let _ = app_clone.emit("audio-meter", metering);
+        thread::sleep(meter_interval);
     });
 }
 
-/// Process transcription result: save to history, type text, and notify user.
-fn process_transcription_result(
+/// Delete a recording's spill file once it is saved or there is nothing to recover.
+fn delete_spill(path: Option<&Path>) {
+    if let Some(path) = path {
+        if let Err(e) = spill::delete(path) {
+            eprintln!("[Failed to delete spill file: {e}]");
+        }
+    }
+}
+
+/// Type or paste the text of a transcript, and notify the user.
+fn output_transcript(
     app: &tauri::AppHandle,
     resources: &Arc<Mutex<AppResources>>,
-    text: &str,
-    language: Language,
-    sample_count: usize,
+    transcript: &Transcript,
 ) {
-    if text.is_empty() {
-        eprintln!("[No speech detected]");
-        return;
-    }
-
-    eprintln!("[Transcribed: {} chars]", text.len());
-
-    // Save to history database
-    let lang_str = match language {
-        Language::English => "en",
-        Language::German => "de",
-    };
-    let history_db = app.state::<Arc<HistoryDb>>();
-    match history_db.save_transcription(text, lang_str, sample_count) {
-        Ok(record) => {
-            eprintln!("[Saved to history: id={}]", record.id);
-            // Emit event for frontend to update
-            let _ = app.emit("transcription-added", &record);
-        }
-        Err(e) => {
-            eprintln!("[Failed to save to history: {e}]");
-        }
-    }
+    let text = transcript.text.as_str();
 
     // Output text based on mode
     let (output_mode, output_result) = {
@@ -210,7 +327,7 @@ fn process_transcription_result(
     };
 
     // Show notification based on result
-    let body = match &output_result {
+    let status = match &output_result {
         Ok(()) => match output_mode {
             OutputMode::Copy => "Copied and pasted",
             OutputMode::Type => "Transcription complete",
@@ -223,6 +340,22 @@ fn process_transcription_result(
             }
         }
     };
+    // Say which language was detected, so a wrong guess is easy to spot
+    let language = match transcript.probability {
+        Some(probability) => Some(format!(
+            "{}, {:.0}%",
+            transcript.language.name(),
+            probability * 100.0
+        )),
+        None => transcript
+            .translated
+            .then(|| transcript.language.name().to_string()),
+    };
+    let body = match (language, transcript.translated) {
+        (Some(language), true) => format!("{status} (translated from {language})"),
+        (Some(language), false) => format!("{status} ({language})"),
+        (None, _) => status.to_string(),
+    };
     let _ = app
         .notification()
         .builder()
@@ -231,72 +364,235 @@ fn process_transcription_result(
         .show();
 }
 
-/// Stop recording, run transcription, and handle the result.
-/// This runs in a background thread.
-fn run_transcription(app: &tauri::AppHandle) {
-    let resources = app.state::<Arc<Mutex<AppResources>>>();
+/// Save a transcription to history, with its recording if `audio_quota_mb` is set.
+/// Only a failure to save the history entry is an error; the recording is best effort.
+pub fn store_transcription(
+    history_db: &HistoryDb,
+    audio_store: &AudioStore,
+    text: &str,
+    language: &str,
+    translated: bool,
+    audio: &[f32],
+    audio_quota_mb: Option<u32>,
+) -> Result<Transcription> {
+    let mut record = history_db.save_transcription(text, language, translated, audio.len())?;
+    eprintln!("[Saved to history: id={}]", record.id);
+    match audio_quota_mb {
+        Some(quota_mb) => save_recording(history_db, audio_store, &mut record, audio, quota_mb),
+        // Recordings saved while it was on still go once their entries leave history
+        None => remove_orphaned_recordings(history_db, audio_store),
+    }
+    Ok(record)
+}
 
-    // Stop recording and get samples + language + hotkeys
-    let (audio, language, hotkey_en, hotkey_mute) = {
-        let res = resources.lock().unwrap();
+/// Delete saved recordings whose history entry is gone.
+fn remove_orphaned_recordings(history_db: &HistoryDb, audio_store: &AudioStore) {
+    let removed = history_db
+        .transcription_ids()
+        .and_then(|ids| audio_store.remove_orphans(&ids));
+    if let Err(e) = removed {
+        eprintln!("[Failed to remove orphaned recordings: {e}]");
+    }
+}
 
-        // Extract hotkeys while we have the lock
-        let hotkey_en = res.hotkey_en.clone();
-        let hotkey_mute = res.hotkey_mute.clone();
+/// Save the recording next to its history entry, then enforce the storage quota.
+fn save_recording(
+    history_db: &HistoryDb,
+    audio_store: &AudioStore,
+    record: &mut Transcription,
+    audio: &[f32],
+    quota_mb: u32,
+) {
+    let path = match audio_store.save(record.id, audio) {
+        Ok(path) => path.to_string_lossy().into_owned(),
+        Err(e) => {
+            eprintln!("[Failed to save recording: {e}]");
+            return;
+        }
+    };
+    if let Err(e) = history_db.set_audio_path(record.id, Some(&path)) {
+        eprintln!("[Failed to link recording: {e}]");
+        return;
+    }
+    record.audio_path = Some(path);
+
+    // Drop recordings of entries that fell out of history, then the oldest over quota
+    let quota_bytes = u64::from(quota_mb) * 1024 * 1024;
+    let deleted = history_db
+        .transcription_ids()
+        .and_then(|ids| audio_store.enforce_quota(quota_bytes, &ids));
+    match deleted {
+        Ok(ids) => {
+            for id in ids {
+                let _ = history_db.set_audio_path(id, None);
+                if id == record.id {
+                    record.audio_path = None;
+                }
+            }
+        }
+        Err(e) => eprintln!("[Failed to enforce audio quota: {e}]"),
+    }
+}
 
-        // Update state to Transcribing
+/// Stop the recorder, transcribe what it captured with `transcribe`, and save the
+/// result to history. `transcribe` is called with the resources still locked, so no
+/// recording starts meanwhile, and returns None without a model.
+pub fn finish_recording<F>(
+    resources: &Mutex<AppResources>,
+    history_db: &HistoryDb,
+    audio_store: &AudioStore,
+    transcribe: F,
+) -> RecordingOutcome
+where
+    F: FnOnce(&AppResources, &[f32], &TranscribeOptions) -> Option<Result<Transcript>>,
+{
+    let (audio, pending, spill_path) = {
+        let mut res = resources.lock().unwrap();
         res.state.set(RecordingState::Transcribing);
 
-        let language = res.pending_language;
+        // Taken with the samples, before another recording can change them
+        let pending = (
+            res.pending_language,
+            res.pending_translate,
+            res.detect_languages.clone(),
+            res.vocabulary.clone(),
+            res.decoding,
+        );
+        let spill_path = res.spill_path.take();
 
         match res.recorder.stop() {
-            Ok(a) => (a, language, hotkey_en, hotkey_mute),
+            Ok(audio) => (audio, pending, spill_path),
             Err(e) => {
+                // The spill file is left for recovery on the next start
                 eprintln!("[Stop error: {e}]");
                 res.state.set(RecordingState::Idle);
-                drop(res); // Release lock before tray update
-                set_tray_state(app, RecordingState::Idle, &hotkey_en, &hotkey_mute);
-                return;
+                return RecordingOutcome::StopFailed(e);
             }
         }
     };
+    let (language, translate, detect_languages, vocabulary, decoding) = pending;
 
-    // Update tray after releasing lock
-    set_tray_state(app, RecordingState::Transcribing, &hotkey_en, &hotkey_mute);
+    eprintln!(
+        "[Transcribing {} samples ({})...]",
+        audio.len(),
+        language.name()
+    );
 
-    eprintln!("[Transcribing {} samples ({language:?})...]", audio.len());
-
-    if audio.is_empty() {
+    let outcome = if audio.is_empty() {
         eprintln!("[No audio captured]");
-    } else {
+        RecordingOutcome::NoSpeech
+    } else if let Some(speech) = vad::trim_silence(&audio) {
         let sample_count = audio.len();
-
-        // Transcribe
+        eprintln!(
+            "[Speech detected: {} of {sample_count} samples]",
+            speech.len()
+        );
+
+        let options = TranscribeOptions {
+            language,
+            detect_among: &detect_languages,
+            translate,
+            vocabulary: &vocabulary,
+            decoding,
+        };
         let transcription = {
             let res = resources.lock().unwrap();
-            if let Some(ref transcriber) = res.transcriber {
-                transcriber.transcribe(&audio, language)
-            } else {
-                Ok(String::new())
-            }
+            transcribe(&res, speech, &options)
         };
 
         match transcription {
-            Ok(text) => {
-                process_transcription_result(app, &resources, &text, language, sample_count);
+            None => {
+                eprintln!("[No model loaded]");
+                RecordingOutcome::NoModel
             }
-            Err(e) => {
+            Some(Err(e)) => {
                 eprintln!("[Transcription error: {e}]");
+                RecordingOutcome::TranscriptionFailed(e)
+            }
+            Some(Ok(transcript)) if transcript.text.is_empty() => {
+                eprintln!("[No speech detected]");
+                RecordingOutcome::NoSpeech
+            }
+            Some(Ok(transcript)) => {
+                eprintln!("[Transcribed: {} chars]", transcript.text.len());
+                let (save_audio, quota_mb) = {
+                    let res = resources.lock().unwrap();
+                    (res.save_audio, res.audio_quota_mb)
+                };
+                let saved = store_transcription(
+                    history_db,
+                    audio_store,
+                    &transcript.text,
+                    transcript.language.code(),
+                    transcript.translated,
+                    &audio,
+                    save_audio.then_some(quota_mb),
+                );
+                let record = saved
+                    .map_err(|e| eprintln!("[Failed to save to history: {e}]"))
+                    .ok();
+                RecordingOutcome::Transcribed { transcript, record }
             }
         }
+    } else {
+        // Skip inference entirely so accidental taps don't produce hallucinated text
+        eprintln!("[No speech detected, skipping transcription]");
+        RecordingOutcome::NoSpeech
+    };
+
+    // Keep the spill file unless the recording is safe in history or has nothing to recover
+    if matches!(
+        outcome,
+        RecordingOutcome::NoSpeech
+            | RecordingOutcome::Transcribed {
+                record: Some(_),
+                ..
+            }
+    ) {
+        delete_spill(spill_path.as_deref());
     }
 
-    // Reset state to Idle
-    {
+    resources.lock().unwrap().state.set(RecordingState::Idle);
+    outcome
+}
+
+/// Stop recording, run transcription, and handle the result.
+/// This runs in a background thread.
+fn run_transcription(app: &tauri::AppHandle) {
+    let resources = app.state::<Arc<Mutex<AppResources>>>();
+    let (hotkey_record, hotkey_mute) = {
         let res = resources.lock().unwrap();
-        res.state.set(RecordingState::Idle);
+        (res.hotkey_record.clone(), res.hotkey_mute.clone())
+    };
+    set_tray_state(
+        app,
+        RecordingState::Transcribing,
+        &hotkey_record,
+        &hotkey_mute,
+    );
+
+    let history_db = app.state::<Arc<HistoryDb>>();
+    let audio_store = app.state::<Arc<AudioStore>>();
+    let outcome = finish_recording(
+        &resources,
+        &history_db,
+        &audio_store,
+        |res, speech, options| {
+            res.transcriber
+                .as_ref()
+                .map(|transcriber| transcriber.transcribe(speech, options))
+        },
+    );
+
+    if let RecordingOutcome::Transcribed { transcript, record } = outcome {
+        if let Some(record) = record {
+            // Emit event for frontend to update
+            let _ = app.emit("transcription-added", &record);
+        }
+        output_transcript(app, &resources, &transcript);
     }
-    set_tray_state(app, RecordingState::Idle, &hotkey_en, &hotkey_mute);
+
+    set_tray_state(app, RecordingState::Idle, &hotkey_record, &hotkey_mute);
 
     // Hide overlay after transcription completes
     if let Some(overlay) = app.get_webview_window("overlay") {
@@ -304,16 +600,53 @@ fn run_transcription(app: &tauri::AppHandle) {
     }
 }
 
+/// Claim the stop of the current recording, discarding it if the hotkey was only tapped.
+pub fn stop_recording(res: &mut AppResources) -> StopOutcome {
+    if res.state.get() != RecordingState::Recording {
+        return StopOutcome::NotRecording;
+    }
+
+    let min_press = Duration::from_millis(u64::from(res.min_press_ms));
+    let pressed = res
+        .recording_started
+        .take()
+        .map_or(Duration::MAX, |started| started.elapsed());
+    if pressed < min_press {
+        eprintln!(
+            "[Hotkey held for {} ms, discarding recording]",
+            pressed.as_millis()
+        );
+        res.recorder.discard();
+        delete_spill(res.spill_path.take().as_deref());
+        res.state.set(RecordingState::Idle);
+        return StopOutcome::Discarded;
+    }
+
+    // Claim the stop so a hotkey release racing an auto-stop can't start a second one
+    res.state.set(RecordingState::Transcribing);
+    StopOutcome::Transcribe
+}
+
 /// Stop recording and spawn transcription thread.
+/// Presses shorter than the minimum are discarded without transcribing or notifying.
 pub fn handle_recording_stop(app: &tauri::AppHandle) {
-    // Check if we're actually recording before spawning the thread
-    {
+    let (outcome, hotkey_record, hotkey_mute) = {
         let resources = app.state::<Arc<Mutex<AppResources>>>();
-        let res = resources.lock().unwrap();
-        if res.state.get() != RecordingState::Recording {
-            // Not recording, nothing to do
+        let mut res = resources.lock().unwrap();
+        let outcome = stop_recording(&mut res);
+        (outcome, res.hotkey_record.clone(), res.hotkey_mute.clone())
+    };
+
+    match outcome {
+        StopOutcome::NotRecording => return,
+        StopOutcome::Discarded => {
+            set_tray_state(app, RecordingState::Idle, &hotkey_record, &hotkey_mute);
+            if let Some(overlay) = app.get_webview_window("overlay") {
+                let _ = overlay.hide();
+            }
             return;
         }
+        StopOutcome::Transcribe => {}
     }
 
     // Switch overlay to spinner mode when hotkey is released
@@ -325,3 +658,334 @@ pub fn handle_recording_stop(app: &tauri::AppHandle) {
         run_transcription(&app_clone);
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::{AudioRecorder, ChannelSelection, DspSettings, GainMode};
+    use crate::audio_store::read_wav;
+    use crate::input::TextInput;
+    use crate::meter::DEFAULT_METER_RATE_HZ;
+    use crate::settings::{AppStateHolder, DEFAULT_HOTKEY_EN, DEFAULT_HOTKEY_MUTE};
+    use crate::source::AudioSource;
+    use crate::test_signal::{sine_wave, SignalClock, SignalSource};
+    use crate::transcribe::{DecodingSettings, Language, WHISPER_SAMPLE_RATE};
+    use crate::vocabulary::Vocabulary;
+    use tempfile::tempdir;
+
+    const ENGLISH: LanguageChoice = LanguageChoice::Fixed(Language::ENGLISH);
+
+    /// Resources recording `signal`, without a model or any UI, and the clock that plays it
+    fn resources(signal: Vec<f32>, min_press_ms: u32) -> (Mutex<AppResources>, SignalClock) {
+        let source = SignalSource::new("test signal", signal, WHISPER_SAMPLE_RATE, 1);
+        resources_from(source, min_press_ms)
+    }
+
+    fn resources_from(
+        source: SignalSource,
+        min_press_ms: u32,
+    ) -> (Mutex<AppResources>, SignalClock) {
+        let clock = source.clock();
+        let mut recorder = AudioRecorder::with_source(
+            Box::new(source),
+            GainMode::manual(1.0),
+            0,
+            ChannelSelection::Average,
+            DspSettings::default(),
+            60,
+        )
+        .unwrap();
+        recorder.set_trailing_ms(0);
+        let resources = Mutex::new(AppResources {
+            recorder,
+            transcriber: None,
+            text_input: TextInput::new(),
+            state: AppStateHolder::new(),
+            pending_language: ENGLISH,
+            pending_translate: false,
+            detect_languages: Vec::new(),
+            vocabulary: Vocabulary::default(),
+            decoding: DecodingSettings::default(),
+            hotkey_record: DEFAULT_HOTKEY_EN.to_string(),
+            hotkey_mute: DEFAULT_HOTKEY_MUTE.to_string(),
+            output_mode: OutputMode::Type,
+            save_audio: true,
+            audio_quota_mb: 100,
+            meter_rate_hz: DEFAULT_METER_RATE_HZ,
+            min_press_ms,
+            recording_started: None,
+            spill_path: None,
+        });
+        (resources, clock)
+    }
+
+    /// Hold the hotkey while `frames` of the signal play
+    fn dictate(
+        resources: &Mutex<AppResources>,
+        clock: &SignalClock,
+        spill_store: &SpillStore,
+        frames: usize,
+    ) -> StopOutcome {
+        start_recording(
+            &mut resources.lock().unwrap(),
+            spill_store,
+            ENGLISH,
+            false,
+            CaptureMode::Microphone,
+        )
+        .unwrap();
+        assert_eq!(
+            resources.lock().unwrap().state.get(),
+            RecordingState::Recording
+        );
+        clock.advance(frames);
+        stop_recording(&mut resources.lock().unwrap())
+    }
+
+    fn transcript(text: &str) -> Transcript {
+        Transcript {
+            text: text.to_string(),
+            language: Language::ENGLISH,
+            probability: None,
+            translated: false,
+        }
+    }
+
+    #[test]
+    fn test_dictation_is_transcribed_and_saved_to_history() {
+        // 0.5s silence, 0.5s tone, 0.5s silence
+        let half_second = WHISPER_SAMPLE_RATE as usize / 2;
+        let mut signal = vec![0.0f32; half_second];
+        signal.extend(sine_wave(220.0, 0.3, WHISPER_SAMPLE_RATE, half_second));
+        signal.extend(vec![0.0f32; half_second]);
+
+        let temp_dir = tempdir().unwrap();
+        let spill_store = SpillStore::new(temp_dir.path()).unwrap();
+        let history_db = HistoryDb::new(temp_dir.path()).unwrap();
+        let audio_store = AudioStore::new(temp_dir.path()).unwrap();
+        let (resources, clock) = resources(signal, 0);
+
+        assert_eq!(
+            dictate(&resources, &clock, &spill_store, 3 * half_second),
+            StopOutcome::Transcribe
+        );
+        // Had the app died now, the recording would be recovered on the next start
+        assert_eq!(SpillStore::new(temp_dir.path()).unwrap().orphans().len(), 1);
+
+        let mut heard = Vec::new();
+        let outcome = finish_recording(
+            &resources,
+            &history_db,
+            &audio_store,
+            |_, speech, options| {
+                assert_eq!(options.language, ENGLISH);
+                heard = speech.to_vec();
+                Some(Ok(transcript("hello world")))
+            },
+        );
+        let RecordingOutcome::Transcribed { transcript, record } = outcome else {
+            panic!("not transcribed: {outcome:?}");
+        };
+        assert_eq!(transcript.text, "hello world");
+
+        // Whisper heard the tone, with the silence around it trimmed
+        let peak = heard.iter().fold(0.0f32, |acc, s| acc.max(s.abs()));
+        assert!((peak - 0.3).abs() < 0.05, "peak = {peak}");
+        assert!(heard[0].abs() < 0.01 && heard[heard.len() - 1].abs() < 0.01);
+
+        let record = record.unwrap();
+        let history = history_db.get_history(10).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].id, record.id);
+        assert_eq!(history[0].text, "hello world");
+        assert_eq!(history[0].audio_path, record.audio_path);
+
+        // The saved recording is the whole take, silence included
+        let (saved, spec) = read_wav(&audio_store.path_for(record.id)).unwrap();
+        assert_eq!(spec.sample_rate, WHISPER_SAMPLE_RATE);
+        assert_eq!(saved.len(), 3 * half_second);
+        assert!(saved.len() > heard.len());
+
+        // Safe in history, so there is nothing left to recover
+        assert!(SpillStore::new(temp_dir.path())
+            .unwrap()
+            .orphans()
+            .is_empty());
+        assert_eq!(resources.lock().unwrap().state.get(), RecordingState::Idle);
+    }
+
+    #[test]
+    fn test_dictation_from_a_wav_file() {
+        let temp_dir = tempdir().unwrap();
+        let spill_store = SpillStore::new(temp_dir.path()).unwrap();
+        let history_db = HistoryDb::new(temp_dir.path()).unwrap();
+        let audio_store = AudioStore::new(temp_dir.path()).unwrap();
+
+        // A stereo 16-bit recording: 0.5s silence, then 0.5s tone on both channels
+        let path = temp_dir.path().join("dictation.wav");
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: WHISPER_SAMPLE_RATE,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let half_second = WHISPER_SAMPLE_RATE as usize / 2;
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        let mut signal = vec![0.0f32; half_second];
+        signal.extend(sine_wave(220.0, 0.3, WHISPER_SAMPLE_RATE, half_second));
+        for sample in signal {
+            #[allow(clippy::cast_possible_truncation)]
+            let sample = (sample * 32767.0) as i16;
+            writer.write_sample(sample).unwrap();
+            writer.write_sample(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let source = SignalSource::from_wav(&path).unwrap();
+        assert_eq!(source.name(), "dictation.wav");
+        let (resources, clock) = resources_from(source, 0);
+
+        // Held past the end of the file, which then plays silence
+        assert_eq!(
+            dictate(&resources, &clock, &spill_store, 3 * half_second),
+            StopOutcome::Transcribe
+        );
+        let mut heard = Vec::new();
+        let outcome = finish_recording(&resources, &history_db, &audio_store, |_, speech, _| {
+            heard = speech.to_vec();
+            Some(Ok(transcript("from a file")))
+        });
+        let RecordingOutcome::Transcribed { record, .. } = outcome else {
+            panic!("not transcribed: {outcome:?}");
+        };
+
+        // Downmixed to mono at full level
+        let peak = heard.iter().fold(0.0f32, |acc, s| acc.max(s.abs()));
+        assert!((peak - 0.3).abs() < 0.05, "peak = {peak}");
+        let (saved, _) = read_wav(&audio_store.path_for(record.unwrap().id)).unwrap();
+        assert_eq!(saved.len(), 3 * half_second);
+    }
+
+    #[test]
+    fn test_silence_is_not_transcribed() {
+        let temp_dir = tempdir().unwrap();
+        let spill_store = SpillStore::new(temp_dir.path()).unwrap();
+        let history_db = HistoryDb::new(temp_dir.path()).unwrap();
+        let audio_store = AudioStore::new(temp_dir.path()).unwrap();
+        let (resources, clock) = resources(vec![0.0f32; WHISPER_SAMPLE_RATE as usize], 0);
+
+        assert_eq!(
+            dictate(
+                &resources,
+                &clock,
+                &spill_store,
+                WHISPER_SAMPLE_RATE as usize / 2
+            ),
+            StopOutcome::Transcribe
+        );
+        let outcome = finish_recording(&resources, &history_db, &audio_store, |_, _, _| {
+            panic!("silence reached the transcriber")
+        });
+
+        assert!(matches!(outcome, RecordingOutcome::NoSpeech), "{outcome:?}");
+        assert!(history_db.get_history(10).unwrap().is_empty());
+        assert!(SpillStore::new(temp_dir.path())
+            .unwrap()
+            .orphans()
+            .is_empty());
+        assert_eq!(resources.lock().unwrap().state.get(), RecordingState::Idle);
+    }
+
+    #[test]
+    fn test_recording_is_kept_for_recovery_without_a_model() {
+        let temp_dir = tempdir().unwrap();
+        let spill_store = SpillStore::new(temp_dir.path()).unwrap();
+        let history_db = HistoryDb::new(temp_dir.path()).unwrap();
+        let audio_store = AudioStore::new(temp_dir.path()).unwrap();
+        let signal = sine_wave(
+            220.0,
+            0.3,
+            WHISPER_SAMPLE_RATE,
+            WHISPER_SAMPLE_RATE as usize,
+        );
+        let (resources, clock) = resources(signal, 0);
+
+        assert_eq!(
+            dictate(
+                &resources,
+                &clock,
+                &spill_store,
+                WHISPER_SAMPLE_RATE as usize
+            ),
+            StopOutcome::Transcribe
+        );
+        let outcome = finish_recording(&resources, &history_db, &audio_store, |_, _, _| None);
+
+        assert!(matches!(outcome, RecordingOutcome::NoModel), "{outcome:?}");
+        assert!(history_db.get_history(10).unwrap().is_empty());
+        assert_eq!(SpillStore::new(temp_dir.path()).unwrap().orphans().len(), 1);
+    }
+
+    #[test]
+    fn test_short_press_is_discarded() {
+        let temp_dir = tempdir().unwrap();
+        let spill_store = SpillStore::new(temp_dir.path()).unwrap();
+        let signal = sine_wave(
+            220.0,
+            0.3,
+            WHISPER_SAMPLE_RATE,
+            WHISPER_SAMPLE_RATE as usize,
+        );
+        let (resources, clock) = resources(signal, 60_000);
+
+        // A muted microphone doesn't start recording at all
+        resources.lock().unwrap().recorder.mute();
+        let started = start_recording(
+            &mut resources.lock().unwrap(),
+            &spill_store,
+            ENGLISH,
+            false,
+            CaptureMode::Microphone,
+        );
+        assert!(matches!(started, Err(StartError::Muted)), "{started:?}");
+        resources.lock().unwrap().recorder.unmute().unwrap();
+
+        assert_eq!(
+            dictate(
+                &resources,
+                &clock,
+                &spill_store,
+                WHISPER_SAMPLE_RATE as usize / 10
+            ),
+            StopOutcome::Discarded
+        );
+        assert_eq!(resources.lock().unwrap().state.get(), RecordingState::Idle);
+        assert!(SpillStore::new(temp_dir.path())
+            .unwrap()
+            .orphans()
+            .is_empty());
+
+        // A second release, e.g. racing the auto-stop, has nothing to stop
+        let outcome = stop_recording(&mut resources.lock().unwrap());
+        assert_eq!(outcome, StopOutcome::NotRecording);
+    }
+
+    #[test]
+    fn test_recording_is_not_saved_when_disabled() {
+        let temp_dir = tempdir().unwrap();
+        let history_db = HistoryDb::new(temp_dir.path()).unwrap();
+        let audio_store = AudioStore::new(temp_dir.path()).unwrap();
+        let audio = sine_wave(
+            220.0,
+            0.3,
+            WHISPER_SAMPLE_RATE,
+            WHISPER_SAMPLE_RATE as usize,
+        );
+        let record =
+            store_transcription(&history_db, &audio_store, "text", "de", false, &audio, None)
+                .unwrap();
+
+        assert!(record.audio_path.is_none());
+        assert!(!audio_store.path_for(record.id).exists());
+    }
+}
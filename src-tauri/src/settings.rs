@@ -2,11 +2,26 @@ use std::sync::atomic::{AtomicU8, Ordering};
 
 use tauri_plugin_store::Store;
 
+use crate::audio::{
+    is_device_id, migrate_device_name, parse_sample_format, CaptureFormat, CaptureMode,
+    ChannelSelection, DspSettings, GainMode, LoopbackSettings, DEFAULT_MAX_RECORDING_SECS,
+    DEFAULT_MIX_GAIN, DEFAULT_PREROLL_MS, DEFAULT_TRAILING_MS, MAX_MIX_GAIN, MAX_PREROLL_MS,
+    MAX_RECORDING_SECS_LIMIT, MAX_TRAILING_MS,
+};
+use crate::audio_store::{DEFAULT_AUDIO_QUOTA_MB, MIN_AUDIO_QUOTA_MB};
+use crate::meter::{DEFAULT_METER_RATE_HZ, MAX_METER_RATE_HZ, MIN_METER_RATE_HZ};
+use crate::transcribe::{DecodingSettings, Language, LanguageChoice, DEFAULT_BEAM_SIZE};
+use crate::vocabulary::Vocabulary;
+
 /// Default hotkey for English transcription
 pub const DEFAULT_HOTKEY_EN: &str = "F2";
 /// Default hotkey for mute toggle
 pub const DEFAULT_HOTKEY_MUTE: &str = "F4";
 
+/// Default and maximum hotkey press below which the recording is discarded
+pub const DEFAULT_MIN_PRESS_MS: u32 = 200;
+pub const MAX_MIN_PRESS_MS: u32 = 1000;
+
 /// Output mode for transcribed text
 #[derive(Debug, Clone, PartialEq)]
 pub enum OutputMode {
@@ -14,41 +29,130 @@ pub enum OutputMode {
     Copy,
 }
 
+/// A push-to-talk hotkey, the language it transcribes and what it records
+#[derive(Debug, Clone, PartialEq)]
+pub struct LanguageHotkey {
+    pub hotkey: String,
+    pub language: LanguageChoice,
+    /// Type the speech translated to English
+    pub translate: bool,
+    pub capture: CaptureMode,
+}
+
 /// Application settings loaded from the store
 #[derive(Debug, Clone)]
 pub struct AppSettings {
-    pub hotkey_en: String,
-    pub hotkey_de: Option<String>,
+    /// Recording hotkeys; never empty, the first one is shown in the tray
+    pub language_hotkeys: Vec<LanguageHotkey>,
     pub hotkey_mute: String,
+    /// Languages automatic detection picks from; empty allows every language
+    pub detect_languages: Vec<Language>,
+    /// Terms passed to Whisper so it spells them right
+    pub vocabulary: Vocabulary,
+    /// Resolved from the decoding preset, or the custom values
+    pub decoding: DecodingSettings,
     pub model_path: Option<String>,
     pub audio_device: Option<String>,
+    pub capture_format: CaptureFormat,
+    pub loopback: LoopbackSettings,
     pub output_mode: OutputMode,
+    pub input_gain: GainMode,
+    pub preroll_ms: u32,
+    pub input_channels: ChannelSelection,
+    pub dsp: DspSettings,
+    pub max_recording_secs: u32,
+    pub trailing_ms: u32,
+    pub min_press_ms: u32,
+    pub save_audio: bool,
+    pub audio_quota_mb: u32,
+    pub playback_device: Option<String>,
+    pub meter_rate_hz: u32,
 }
 
 impl AppSettings {
     /// Load settings from a Tauri store
     pub fn load<R: tauri::Runtime>(store: &Store<R>) -> Self {
-        let hotkey_en = store
-            .get("hotkey")
-            .and_then(|v| v.as_str().map(String::from))
-            .unwrap_or_else(|| DEFAULT_HOTKEY_EN.to_string());
-
-        let hotkey_de = store
-            .get("hotkey_de")
-            .and_then(|v| v.as_str().map(String::from));
+        // Entries with no hotkey or an unknown language are skipped
+        let language_hotkeys = store
+            .get("language_hotkeys")
+            .and_then(|v| {
+                v.as_array()
+                    .map(|hotkeys| hotkeys.iter().filter_map(parse_language_hotkey).collect())
+            })
+            .unwrap_or_else(|| migrate_language_hotkeys(store));
+        let language_hotkeys = if language_hotkeys.is_empty() {
+            vec![LanguageHotkey {
+                hotkey: DEFAULT_HOTKEY_EN.to_string(),
+                language: LanguageChoice::Fixed(Language::ENGLISH),
+                translate: false,
+                capture: CaptureMode::default(),
+            }]
+        } else {
+            language_hotkeys
+        };
 
         let hotkey_mute = store
             .get("hotkey_mute")
             .and_then(|v| v.as_str().map(String::from))
             .unwrap_or_else(|| DEFAULT_HOTKEY_MUTE.to_string());
 
+        // Unknown codes are skipped
+        let detect_languages = store
+            .get("detect_languages")
+            .and_then(|v| {
+                v.as_array().map(|codes| {
+                    codes
+                        .iter()
+                        .filter_map(|code| Language::from_code(code.as_str()?))
+                        .collect()
+                })
+            })
+            .unwrap_or_default();
+
+        let vocabulary = store
+            .get("vocabulary")
+            .map(|v| Vocabulary::from_value(&v))
+            .unwrap_or_default();
+
+        // "fast" (the default) and "accurate" are presets; "custom" reads the values
+        let decoding = match store
+            .get("decoding_preset")
+            .and_then(|v| v.as_str().map(String::from))
+            .as_deref()
+        {
+            Some("accurate") => DecodingSettings::ACCURATE,
+            Some("custom") => load_custom_decoding(store),
+            _ => DecodingSettings::FAST,
+        };
+
         let model_path = store
             .get("model_path")
             .and_then(|v| v.as_str().map(String::from));
 
         let audio_device = store
             .get("audio_device")
-            .and_then(|v| v.as_str().map(String::from));
+            .and_then(|v| v.as_str().map(String::from))
+            .map(|device| migrate_audio_device(store, device));
+
+        let capture_format = load_capture_format(store);
+
+        #[allow(clippy::cast_possible_truncation)]
+        let mix_gain = |key: &str| {
+            store
+                .get(key)
+                .and_then(|v| v.as_f64())
+                .map_or(DEFAULT_MIX_GAIN, |gain| {
+                    (gain as f32).clamp(0.0, MAX_MIX_GAIN)
+                })
+        };
+        let loopback = LoopbackSettings {
+            device: store
+                .get("loopback_device")
+                .and_then(|v| v.as_str().map(String::from))
+                .filter(|device| !device.is_empty()),
+            mic_gain: mix_gain("mix_mic_gain"),
+            loopback_gain: mix_gain("mix_loopback_gain"),
+        };
 
         let output_mode = store
             .get("output_mode")
@@ -60,14 +164,269 @@ impl AppSettings {
             })
             .unwrap_or_else(|| OutputMode::Type);
 
+        // A number is a manual gain override; anything else means automatic gain
+        #[allow(clippy::cast_possible_truncation)]
+        let input_gain = store
+            .get("input_gain")
+            .and_then(|v| v.as_f64())
+            .map_or(GainMode::Auto, |gain| GainMode::manual(gain as f32));
+
+        let preroll_ms = store
+            .get("preroll_ms")
+            .and_then(|v| v.as_u64())
+            .and_then(|ms| u32::try_from(ms).ok())
+            .map_or(DEFAULT_PREROLL_MS, |ms| ms.min(MAX_PREROLL_MS));
+
+        // A number selects one channel, an array a subset; anything else averages all
+        let input_channels = store
+            .get("input_channels")
+            .map_or(ChannelSelection::Average, |v| {
+                if let Some(channel) = v.as_u64() {
+                    usize::try_from(channel)
+                        .map_or(ChannelSelection::Average, ChannelSelection::Single)
+                } else if let Some(channels) = v.as_array() {
+                    ChannelSelection::Subset(
+                        channels
+                            .iter()
+                            .filter_map(|c| c.as_u64().and_then(|c| usize::try_from(c).ok()))
+                            .collect(),
+                    )
+                } else {
+                    ChannelSelection::Average
+                }
+            });
+
+        let flag = |key: &str| store.get(key).and_then(|v| v.as_bool()).unwrap_or(false);
+        let dsp = DspSettings {
+            high_pass: flag("dsp_high_pass"),
+            noise_gate: flag("dsp_noise_gate"),
+            denoise: flag("dsp_denoise"),
+        };
+
+        let max_recording_secs = store
+            .get("max_recording_secs")
+            .and_then(|v| v.as_u64())
+            .and_then(|secs| u32::try_from(secs).ok())
+            .map_or(DEFAULT_MAX_RECORDING_SECS, |secs| {
+                secs.clamp(1, MAX_RECORDING_SECS_LIMIT)
+            });
+
+        let trailing_ms = store
+            .get("trailing_capture_ms")
+            .and_then(|v| v.as_u64())
+            .and_then(|ms| u32::try_from(ms).ok())
+            .map_or(DEFAULT_TRAILING_MS, |ms| ms.min(MAX_TRAILING_MS));
+
+        let min_press_ms = store
+            .get("min_press_ms")
+            .and_then(|v| v.as_u64())
+            .and_then(|ms| u32::try_from(ms).ok())
+            .map_or(DEFAULT_MIN_PRESS_MS, |ms| ms.min(MAX_MIN_PRESS_MS));
+
+        let save_audio = store
+            .get("save_audio")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+
+        let audio_quota_mb = store
+            .get("audio_quota_mb")
+            .and_then(|v| v.as_u64())
+            .and_then(|mb| u32::try_from(mb).ok())
+            .map_or(DEFAULT_AUDIO_QUOTA_MB, |mb| mb.max(MIN_AUDIO_QUOTA_MB));
+
+        let playback_device = store
+            .get("playback_device")
+            .and_then(|v| v.as_str().map(String::from))
+            .filter(|device| !device.is_empty());
+
+        let meter_rate_hz = store
+            .get("meter_rate_hz")
+            .and_then(|v| v.as_u64())
+            .and_then(|hz| u32::try_from(hz).ok())
+            .map_or(DEFAULT_METER_RATE_HZ, |hz| {
+                hz.clamp(MIN_METER_RATE_HZ, MAX_METER_RATE_HZ)
+            });
+
         Self {
-            hotkey_en,
-            hotkey_de,
+            language_hotkeys,
             hotkey_mute,
+            detect_languages,
+            vocabulary,
+            decoding,
             model_path,
             audio_device,
+            capture_format,
+            loopback,
             output_mode,
+            input_gain,
+            preroll_ms,
+            input_channels,
+            dsp,
+            max_recording_secs,
+            trailing_ms,
+            min_press_ms,
+            save_audio,
+            audio_quota_mb,
+            playback_device,
+            meter_rate_hz,
+        }
+    }
+
+    /// The hotkey shown in the tray tooltip
+    pub fn primary_hotkey(&self) -> &str {
+        self.language_hotkeys
+            .first()
+            .map_or(DEFAULT_HOTKEY_EN, |hotkey| &hotkey.hotkey)
+    }
+}
+
+/// Read only the capture format, for callers that don't need the rest of the
+/// settings and their migrations.
+pub fn load_capture_format<R: tauri::Runtime>(store: &Store<R>) -> CaptureFormat {
+    // Zero, an empty string or a missing key keeps the device default
+    let positive = |key: &str| {
+        store
+            .get(key)
+            .and_then(|v| v.as_u64())
+            .and_then(|n| u32::try_from(n).ok())
+            .filter(|&n| n > 0)
+    };
+    CaptureFormat {
+        sample_rate: positive("capture_sample_rate"),
+        sample_format: store
+            .get("capture_sample_format")
+            .and_then(|v| v.as_str().and_then(parse_sample_format)),
+        buffer_size: positive("capture_buffer_size"),
+    }
+}
+
+/// Read custom decoding settings; missing values keep the fast preset's and
+/// out-of-range values are clamped.
+fn load_custom_decoding<R: tauri::Runtime>(store: &Store<R>) -> DecodingSettings {
+    let defaults = DecodingSettings::FAST;
+    #[allow(clippy::cast_possible_truncation)]
+    let number = |key: &str, default: f32| {
+        store
+            .get(key)
+            .and_then(|v| v.as_f64())
+            .map_or(default, |n| n as f32)
+    };
+    let flag =
+        |key: &str, default: bool| store.get(key).and_then(|v| v.as_bool()).unwrap_or(default);
+
+    let beam_size = store
+        .get("decoding_beam_size")
+        .and_then(|v| v.as_u64())
+        .and_then(|size| u32::try_from(size).ok())
+        .unwrap_or(DEFAULT_BEAM_SIZE);
+
+    DecodingSettings {
+        beam_size: flag("decoding_beam_search", false).then_some(beam_size),
+        temperature: number("decoding_temperature", defaults.temperature),
+        temperature_inc: number("decoding_temperature_inc", defaults.temperature_inc),
+        entropy_threshold: number("decoding_entropy_threshold", defaults.entropy_threshold),
+        logprob_threshold: number("decoding_logprob_threshold", defaults.logprob_threshold),
+        no_speech_threshold: number("decoding_no_speech_threshold", defaults.no_speech_threshold),
+        no_context: flag("decoding_no_context", defaults.no_context),
+        single_segment: flag("decoding_single_segment", defaults.single_segment),
+        suppress_blank: flag("decoding_suppress_blank", defaults.suppress_blank),
+    }
+    .validated()
+}
+
+/// Parse a `language_hotkeys` entry: `{ "hotkey", "language", "translate", "capture" }`,
+/// where the language is a code or "auto".
+fn parse_language_hotkey(value: &serde_json::Value) -> Option<LanguageHotkey> {
+    let hotkey = value.get("hotkey")?.as_str().filter(|h| !h.is_empty())?;
+    let language = LanguageChoice::from_code(value.get("language")?.as_str()?)?;
+    let translate = value
+        .get("translate")
+        .and_then(serde_json::Value::as_bool)
+        .unwrap_or(false);
+    let capture = value
+        .get("capture")
+        .and_then(|v| v.as_str())
+        .map_or(CaptureMode::default(), parse_capture_mode);
+    Some(LanguageHotkey {
+        hotkey: hotkey.to_string(),
+        language,
+        translate,
+        capture,
+    })
+}
+
+fn parse_capture_mode(name: &str) -> CaptureMode {
+    match name {
+        "loopback" => CaptureMode::Loopback,
+        "mix" => CaptureMode::Mix,
+        _ => CaptureMode::Microphone,
+    }
+}
+
+fn capture_mode_name(mode: CaptureMode) -> &'static str {
+    match mode {
+        CaptureMode::Microphone => "microphone",
+        CaptureMode::Loopback => "loopback",
+        CaptureMode::Mix => "mix",
+    }
+}
+
+/// Older versions had a fixed English hotkey and an optional German one;
+/// turn them into `language_hotkeys` entries.
+fn migrate_language_hotkeys<R: tauri::Runtime>(store: &Store<R>) -> Vec<LanguageHotkey> {
+    let string = |key: &str| {
+        store
+            .get(key)
+            .and_then(|v| v.as_str().map(String::from))
+            .filter(|s| !s.is_empty())
+    };
+    let capture =
+        |key: &str| string(key).map_or(CaptureMode::default(), |s| parse_capture_mode(&s));
+
+    let mut hotkeys = vec![LanguageHotkey {
+        hotkey: string("hotkey").unwrap_or_else(|| DEFAULT_HOTKEY_EN.to_string()),
+        language: LanguageChoice::Fixed(Language::ENGLISH),
+        translate: false,
+        capture: capture("hotkey_capture"),
+    }];
+    if let (Some(hotkey), Some(german)) = (string("hotkey_de"), LanguageChoice::from_code("de")) {
+        hotkeys.push(LanguageHotkey {
+            hotkey,
+            language: german,
+            translate: false,
+            capture: capture("hotkey_de_capture"),
+        });
+    }
+
+    store.set(
+        "language_hotkeys",
+        hotkeys
+            .iter()
+            .map(|hotkey| {
+                serde_json::json!({
+                    "hotkey": hotkey.hotkey,
+                    "language": hotkey.language.code(),
+                    "translate": hotkey.translate,
+                    "capture": capture_mode_name(hotkey.capture),
+                })
+            })
+            .collect::<Vec<_>>(),
+    );
+    hotkeys
+}
+
+/// Older versions stored the device display name; replace it with the stable id.
+/// The name is kept if the device isn't connected so migration can be retried later.
+fn migrate_audio_device<R: tauri::Runtime>(store: &Store<R>, device: String) -> String {
+    if device.is_empty() || is_device_id(&device) {
+        return device;
+    }
+    match migrate_device_name(&device) {
+        Some(id) => {
+            store.set("audio_device", id.clone());
+            id
         }
+        None => device,
     }
 }
 
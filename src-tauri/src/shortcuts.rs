@@ -4,15 +4,17 @@ use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutState};
 
 use crate::error::{Error, Result};
 use crate::recording::{handle_mute_toggle, handle_recording_start, handle_recording_stop};
-use crate::settings::AppSettings;
-use crate::transcribe::Language;
+use crate::settings::{AppSettings, LanguageHotkey};
 
-/// Setup a recording shortcut for a specific language.
-pub fn setup_shortcut(
-    app: &tauri::AppHandle,
-    shortcut_str: &str,
-    language: Language,
-) -> Result<()> {
+/// Setup a recording shortcut for its language, translation and capture mode.
+pub fn setup_shortcut(app: &tauri::AppHandle, hotkey: &LanguageHotkey) -> Result<()> {
+    let shortcut_str = hotkey.hotkey.as_str();
+    let LanguageHotkey {
+        language,
+        translate,
+        capture,
+        ..
+    } = *hotkey;
     let shortcut: Shortcut = shortcut_str
         .parse()
         .map_err(|e| Error::Hotkey(format!("invalid shortcut '{shortcut_str}': {e}")))?;
@@ -24,7 +26,7 @@ pub fn setup_shortcut(
 
             match event.state {
                 ShortcutState::Pressed => {
-                    handle_recording_start(&app, language);
+                    handle_recording_start(&app, language, translate, capture);
                 }
                 ShortcutState::Released => {
                     handle_recording_stop(&app);
@@ -33,7 +35,11 @@ pub fn setup_shortcut(
         })
         .map_err(|e| Error::Hotkey(format!("failed to register shortcut: {e}")))?;
 
-    eprintln!("[Shortcut registered: {shortcut_str} ({language:?})]");
+    eprintln!(
+        "[Shortcut registered: {shortcut_str} ({}{}, {capture:?})]",
+        language.name(),
+        if translate { " to English" } else { "" }
+    );
     Ok(())
 }
 
@@ -57,7 +63,7 @@ pub fn setup_mute_shortcut(app: &tauri::AppHandle, shortcut_str: &str) -> Result
     Ok(())
 }
 
-/// Register all shortcuts (English, German if configured, and mute) from settings.
+/// Register all shortcuts (every language hotkey and mute) from settings.
 /// This unregisters all existing shortcuts first.
 pub fn register_all_shortcuts(
     app: &tauri::AppHandle,
@@ -69,14 +75,9 @@ pub fn register_all_shortcuts(
         .unregister_all()
         .map_err(|e| format!("failed to unregister shortcuts: {e}"))?;
 
-    // Register English shortcut
-    setup_shortcut(app, &settings.hotkey_en, Language::English).map_err(|e| e.to_string())?;
-
-    // Register German shortcut if configured
-    if let Some(ref hotkey) = settings.hotkey_de {
-        if !hotkey.is_empty() {
-            setup_shortcut(app, hotkey, Language::German).map_err(|e| e.to_string())?;
-        }
+    // Register the language shortcuts
+    for hotkey in &settings.language_hotkeys {
+        setup_shortcut(app, hotkey).map_err(|e| e.to_string())?;
     }
 
     // Register mute shortcut
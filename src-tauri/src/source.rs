@@ -0,0 +1,161 @@
+//! Audio sources feeding the recorder. Devices are opened through cpal; the tests
+//! feed it from memory instead, see `test_signal::SignalSource`.
+
+use cpal::traits::{DeviceTrait, StreamTrait};
+use cpal::{Device, FromSample, SampleFormat, Stream, StreamConfig, StreamError};
+
+use crate::audio::{
+    get_device_name, select_input_config, select_loopback_config, CaptureFormat, SampleSink,
+};
+use crate::error::{Error, Result};
+
+/// Call a stream builder that is generic over the sample type with the type of a
+/// cpal `SampleFormat`, e.g. `dispatch_sample_format!(format, build(device, config))`
+/// calls `build::<i16>(device, config)` for `SampleFormat::I16`.
+macro_rules! dispatch_sample_format {
+    ($format:expr, $build:ident($($arg:expr),* $(,)?)) => {
+        match $format {
+            cpal::SampleFormat::F32 => $build::<f32>($($arg),*),
+            cpal::SampleFormat::F64 => $build::<f64>($($arg),*),
+            cpal::SampleFormat::I8 => $build::<i8>($($arg),*),
+            cpal::SampleFormat::I16 => $build::<i16>($($arg),*),
+            cpal::SampleFormat::I32 => $build::<i32>($($arg),*),
+            cpal::SampleFormat::U8 => $build::<u8>($($arg),*),
+            cpal::SampleFormat::U16 => $build::<u16>($($arg),*),
+            format => Err($crate::error::Error::Audio(format!(
+                "unsupported sample format: {format:?}"
+            ))),
+        }
+    };
+}
+pub(crate) use dispatch_sample_format;
+
+/// Something the recorder captures interleaved audio from
+pub trait AudioSource: Send {
+    /// Name shown to the user, e.g. the device name
+    fn name(&self) -> String;
+
+    fn sample_rate(&self) -> u32;
+
+    fn channels(&self) -> usize;
+
+    /// Start delivering audio to `sink`, replacing the previous sink.
+    fn start(&mut self, sink: SampleSink) -> Result<()>;
+
+    /// Stop delivering audio and release the device.
+    fn stop(&mut self);
+
+    fn is_running(&self) -> bool;
+}
+
+/// An input device opened through cpal
+pub struct CpalSource {
+    device: Device,
+    config: StreamConfig,
+    sample_format: SampleFormat,
+    stream: Option<Stream>,
+}
+
+impl CpalSource {
+    /// Prepare `device` with the config closest to `format`. Nothing is opened until `start()`.
+    pub fn new(device: Device, format: CaptureFormat) -> Result<Self> {
+        let (config, sample_format) = select_input_config(&device, format)?;
+        Ok(Self::with_config(device, config, sample_format))
+    }
+
+    /// Prepare `device` for recording system audio, see `select_loopback_config()`.
+    pub fn loopback(device: Device) -> Result<Self> {
+        let (config, sample_format) = select_loopback_config(&device)?;
+        Ok(Self::with_config(device, config, sample_format))
+    }
+
+    fn with_config(device: Device, config: StreamConfig, sample_format: SampleFormat) -> Self {
+        eprintln!(
+            "[Audio config for {}: sample_rate={}, channels={}, format={:?}, buffer={:?}]",
+            get_device_name(&device),
+            config.sample_rate,
+            config.channels,
+            sample_format,
+            config.buffer_size
+        );
+
+        Self {
+            device,
+            config,
+            sample_format,
+            stream: None,
+        }
+    }
+}
+
+impl AudioSource for CpalSource {
+    fn name(&self) -> String {
+        get_device_name(&self.device)
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.config.sample_rate
+    }
+
+    fn channels(&self) -> usize {
+        usize::from(self.config.channels)
+    }
+
+    fn start(&mut self, sink: SampleSink) -> Result<()> {
+        // Release the previous stream before opening the device again
+        self.stream = None;
+
+        let (device, config) = (&self.device, &self.config);
+        let stream =
+            dispatch_sample_format!(self.sample_format, build_input_stream(device, config, sink))?;
+
+        stream
+            .play()
+            .map_err(|e| Error::Audio(format!("failed to start stream: {e}")))?;
+
+        self.stream = Some(stream);
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        self.stream = None;
+    }
+
+    fn is_running(&self) -> bool {
+        self.stream.is_some()
+    }
+}
+
+/// Build an input stream whose callback only pushes into the lock-free ring buffer
+/// and updates atomics, so it never blocks on the recorder.
+fn build_input_stream<T>(
+    device: &Device,
+    config: &StreamConfig,
+    mut sink: SampleSink,
+) -> Result<Stream>
+where
+    T: cpal::Sample + cpal::SizedSample,
+    f32: FromSample<T>,
+{
+    let failure = sink.failure();
+    device
+        .build_input_stream(
+            config,
+            move |data: &[T], _: &cpal::InputCallbackInfo| {
+                // Store raw samples; gain is applied per recording in `stop()`
+                sink.push(data);
+            },
+            move |err| {
+                eprintln!("audio stream error: {err}");
+                // Flag streams that can't recover on their own; the device monitor rebuilds them
+                if matches!(
+                    err,
+                    StreamError::DeviceNotAvailable | StreamError::StreamInvalidated
+                ) {
+                    failure.report();
+                }
+            },
+            None,
+        )
+        .map_err(|e| Error::Audio(format!("failed to build input stream: {e}")))
+}
@@ -0,0 +1,292 @@
+//! Crash-safe spill of the recording in progress. Its 16kHz mono samples are appended to
+//! a file while recording, so a dictation survives the app crashing or being killed.
+//! System audio goes to a companion file next to it and is mixed in on recovery.
+
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+use crate::transcribe::WHISPER_SAMPLE_RATE;
+
+/// Subdirectory of the app data dir holding recordings in progress
+const SPILL_DIR: &str = "spill";
+
+/// Spill files hold raw little-endian f32 samples
+const SPILL_EXTENSION: &str = "f32";
+
+/// Companion files hold the system audio of the same recording, as raw f32 samples
+const LOOPBACK_EXTENSION: &str = "loopback";
+
+/// File name suffix of recordings that are to be translated
+const TRANSLATE_SUFFIX: &str = "_translate";
+
+/// A recording left behind by a run that ended before it was saved
+#[derive(Debug, Clone, Serialize)]
+pub struct OrphanedRecording {
+    /// File stem, `<unix millis>_<language>`, with `_translate` for translations
+    pub id: String,
+    pub language: String,
+    pub translate: bool,
+    pub created_at: String,
+    pub duration_ms: u64,
+    #[serde(skip)]
+    path: PathBuf,
+    // System audio recorded alongside, if any
+    #[serde(skip)]
+    loopback_path: Option<PathBuf>,
+}
+
+impl OrphanedRecording {
+    fn from_path(path: PathBuf) -> Option<Self> {
+        if path.extension()? != SPILL_EXTENSION {
+            return None;
+        }
+        let id = path.file_stem()?.to_str()?.to_string();
+        let (millis, language) = id.split_once('_')?;
+        let (language, translate) = match language.strip_suffix(TRANSLATE_SUFFIX) {
+            Some(language) => (language, true),
+            None => (language, false),
+        };
+        let created_at = DateTime::<Utc>::from_timestamp_millis(millis.parse().ok()?)?;
+        let loopback_path = Some(path.with_extension(LOOPBACK_EXTENSION)).filter(|p| p.exists());
+        let bytes = [Some(&path), loopback_path.as_ref()]
+            .into_iter()
+            .flatten()
+            .filter_map(|p| std::fs::metadata(p).ok())
+            .map(|metadata| metadata.len())
+            .max()?;
+        let samples = bytes / 4;
+
+        Some(Self {
+            language: language.to_string(),
+            translate,
+            created_at: created_at.to_rfc3339(),
+            duration_ms: samples * 1000 / u64::from(WHISPER_SAMPLE_RATE),
+            id,
+            path,
+            loopback_path,
+        })
+    }
+}
+
+/// The spill file of the recording in progress
+pub struct SpillFile {
+    file: File,
+    path: PathBuf,
+}
+
+impl SpillFile {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Create the companion file for the system audio of the same recording.
+    pub fn loopback(&self) -> Result<SpillFile> {
+        let path = self.path.with_extension(LOOPBACK_EXTENSION);
+        let file = File::create(&path)
+            .map_err(|e| Error::Storage(format!("failed to create {}: {e}", path.display())))?;
+        Ok(SpillFile { file, path })
+    }
+
+    /// Append 16kHz mono samples. Each call goes straight to the OS, so everything
+    /// appended is on disk even if the process dies right after.
+    pub fn append(&mut self, samples: &[f32]) -> Result<()> {
+        if samples.is_empty() {
+            return Ok(());
+        }
+        let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        self.file
+            .write_all(&bytes)
+            .map_err(|e| Error::Storage(format!("failed to spill recording: {e}")))
+    }
+}
+
+/// Keeps recordings in progress on disk until they are saved to history
+pub struct SpillStore {
+    dir: PathBuf,
+    // Recordings found on startup that haven't been recovered or discarded yet
+    orphans: Mutex<Vec<OrphanedRecording>>,
+}
+
+impl SpillStore {
+    /// Open the spill directory, creating it if needed, and collect the recordings
+    /// a previous run left behind
+    pub fn new(app_data_dir: &Path) -> Result<Self> {
+        let dir = app_data_dir.join(SPILL_DIR);
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| Error::Storage(format!("failed to create spill dir: {e}")))?;
+
+        // Spilled audio is as sensitive as saved recordings (owner access only)
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let _ = std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700));
+        }
+
+        let entries = std::fs::read_dir(&dir)
+            .map_err(|e| Error::Storage(format!("failed to read spill dir: {e}")))?;
+        let mut orphans: Vec<_> = entries
+            .filter_map(std::result::Result::ok)
+            .filter_map(|entry| OrphanedRecording::from_path(entry.path()))
+            .collect();
+        orphans.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+
+        Ok(Self {
+            dir,
+            orphans: Mutex::new(orphans),
+        })
+    }
+
+    /// Start the spill file for a new recording in `language`.
+    pub fn create(&self, language: &str, translate: bool) -> Result<SpillFile> {
+        let suffix = if translate { TRANSLATE_SUFFIX } else { "" };
+        let path = self.dir.join(format!(
+            "{}_{language}{suffix}.{SPILL_EXTENSION}",
+            Utc::now().timestamp_millis()
+        ));
+        let file = File::create(&path)
+            .map_err(|e| Error::Storage(format!("failed to create {}: {e}", path.display())))?;
+        Ok(SpillFile { file, path })
+    }
+
+    /// Recordings left behind by a previous run, oldest first
+    pub fn orphans(&self) -> Vec<OrphanedRecording> {
+        self.orphans.lock().unwrap().clone()
+    }
+
+    pub fn orphan(&self, id: &str) -> Option<OrphanedRecording> {
+        self.orphans
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|orphan| orphan.id == id)
+            .cloned()
+    }
+
+    /// Read the 16kHz mono microphone samples of an orphaned recording.
+    pub fn read(&self, orphan: &OrphanedRecording) -> Result<Vec<f32>> {
+        read_samples(&orphan.path)
+    }
+
+    /// Read the 16kHz mono system audio of an orphaned recording, if it has any.
+    pub fn read_loopback(&self, orphan: &OrphanedRecording) -> Result<Option<Vec<f32>>> {
+        orphan
+            .loopback_path
+            .as_deref()
+            .map(read_samples)
+            .transpose()
+    }
+
+    /// Delete an orphaned recording once it has been recovered or discarded.
+    pub fn remove_orphan(&self, id: &str) -> Result<()> {
+        let mut orphans = self.orphans.lock().unwrap();
+        if let Some(index) = orphans.iter().position(|orphan| orphan.id == id) {
+            delete(&orphans[index].path)?;
+            orphans.remove(index);
+        }
+        Ok(())
+    }
+}
+
+fn read_samples(path: &Path) -> Result<Vec<f32>> {
+    let bytes = std::fs::read(path)
+        .map_err(|e| Error::Storage(format!("failed to read {}: {e}", path.display())))?;
+    // A crash mid-write can leave a partial sample at the end; it is dropped
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect())
+}
+
+/// Delete a spill file and its system audio companion. A missing file is not an error.
+pub fn delete(path: &Path) -> Result<()> {
+    remove_file(&path.with_extension(LOOPBACK_EXTENSION))?;
+    remove_file(path)
+}
+
+fn remove_file(path: &Path) -> Result<()> {
+    match std::fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(Error::Storage(format!(
+            "failed to delete {}: {e}",
+            path.display()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_spilled_recording_is_found_after_restart() {
+        let temp_dir = tempdir().unwrap();
+        let store = SpillStore::new(temp_dir.path()).unwrap();
+        assert!(store.orphans().is_empty());
+
+        let mut spill = store.create("de", true).unwrap();
+        let second = vec![0.25f32; WHISPER_SAMPLE_RATE as usize];
+        spill.append(&second).unwrap();
+        spill.append(&[0.5, -0.5]).unwrap();
+        drop(spill);
+
+        // A recording in progress isn't an orphan until the next start
+        assert!(store.orphans().is_empty());
+
+        let store = SpillStore::new(temp_dir.path()).unwrap();
+        let orphans = store.orphans();
+        assert_eq!(orphans.len(), 1);
+        assert_eq!(orphans[0].language, "de");
+        assert!(orphans[0].translate);
+        assert_eq!(orphans[0].duration_ms, 1000);
+
+        let samples = store.read(&orphans[0]).unwrap();
+        assert_eq!(samples.len(), second.len() + 2);
+        assert_eq!(&samples[second.len()..], &[0.5, -0.5]);
+        assert!(store.read_loopback(&orphans[0]).unwrap().is_none());
+
+        store.remove_orphan(&orphans[0].id).unwrap();
+        assert!(store.orphans().is_empty());
+        assert!(SpillStore::new(temp_dir.path())
+            .unwrap()
+            .orphans()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_system_audio_is_recovered_with_the_microphone() {
+        let temp_dir = tempdir().unwrap();
+        let store = SpillStore::new(temp_dir.path()).unwrap();
+        let mut spill = store.create("en", false).unwrap();
+        let mut loopback = spill.loopback().unwrap();
+        spill.append(&[0.5; 4]).unwrap();
+        loopback
+            .append(&vec![0.25; 2 * WHISPER_SAMPLE_RATE as usize])
+            .unwrap();
+        drop((spill, loopback));
+
+        // Only the microphone file is listed; the longer system audio sets the duration
+        let store = SpillStore::new(temp_dir.path()).unwrap();
+        let orphans = store.orphans();
+        assert_eq!(orphans.len(), 1);
+        assert_eq!(orphans[0].duration_ms, 2000);
+        assert_eq!(store.read(&orphans[0]).unwrap(), vec![0.5; 4]);
+        let system = store.read_loopback(&orphans[0]).unwrap().unwrap();
+        assert_eq!(system.len(), 2 * WHISPER_SAMPLE_RATE as usize);
+
+        store.remove_orphan(&orphans[0].id).unwrap();
+        assert_eq!(
+            std::fs::read_dir(temp_dir.path().join(SPILL_DIR))
+                .unwrap()
+                .count(),
+            0
+        );
+    }
+}
@@ -0,0 +1,145 @@
+//! Synthetic signals shared by the tests, and a source that feeds them to the recorder
+//! so the recording pipeline runs without a sound card.
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::audio::SampleSink;
+use crate::audio_store::read_wav;
+use crate::error::Result;
+use crate::source::AudioSource;
+
+/// `frames` samples of a mono sine tone.
+pub fn sine_wave(freq: f32, amplitude: f32, sample_rate: u32, frames: usize) -> Vec<f32> {
+    #[allow(clippy::cast_precision_loss)]
+    let samples = (0..frames)
+        .map(|i| {
+            let t = i as f32 / sample_rate as f32;
+            amplitude * (2.0 * std::f32::consts::PI * freq * t).sin()
+        })
+        .collect();
+    samples
+}
+
+/// Interleaved audio from memory, delivered when the test advances its clock rather
+/// than on the wall clock, so tests don't depend on timing. Once the signal runs out
+/// the source delivers silence, like an open microphone.
+pub struct SignalSource {
+    name: String,
+    sample_rate: u32,
+    channels: usize,
+    feed: Arc<Mutex<Feed>>,
+}
+
+struct Feed {
+    samples: Vec<f32>,
+    channels: usize,
+    delivered_frames: usize,
+    // Set while the source is started
+    sink: Option<SampleSink>,
+}
+
+impl SignalSource {
+    pub fn new(name: &str, samples: Vec<f32>, sample_rate: u32, channels: usize) -> Self {
+        let channels = channels.max(1);
+        Self {
+            name: name.to_string(),
+            sample_rate,
+            channels,
+            feed: Arc::new(Mutex::new(Feed {
+                samples,
+                channels,
+                delivered_frames: 0,
+                sink: None,
+            })),
+        }
+    }
+
+    /// Play a WAV file at its own rate and channel count.
+    pub fn from_wav(path: &Path) -> Result<Self> {
+        let (samples, spec) = read_wav(path)?;
+        let name = path.file_name().map_or_else(
+            || path.display().to_string(),
+            |n| n.to_string_lossy().into_owned(),
+        );
+        Ok(Self::new(
+            &name,
+            samples,
+            spec.sample_rate,
+            usize::from(spec.channels),
+        ))
+    }
+
+    /// A sine tone of `duration`, the same on every channel.
+    pub fn sine(
+        freq: f32,
+        amplitude: f32,
+        duration: Duration,
+        sample_rate: u32,
+        channels: usize,
+    ) -> Self {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let frames = (duration.as_secs_f64() * f64::from(sample_rate)) as usize;
+        let samples = sine_wave(freq, amplitude, sample_rate, frames)
+            .into_iter()
+            .flat_map(|value| std::iter::repeat_n(value, channels))
+            .collect();
+        Self::new(&format!("{freq} Hz sine"), samples, sample_rate, channels)
+    }
+
+    /// The clock that delivers this source's audio, kept by the test once the
+    /// source is handed to the recorder.
+    pub fn clock(&self) -> SignalClock {
+        SignalClock(self.feed.clone())
+    }
+}
+
+/// Moves a `SignalSource` forward in time
+#[derive(Clone)]
+pub struct SignalClock(Arc<Mutex<Feed>>);
+
+impl SignalClock {
+    /// Deliver the next `frames` frames right away, as a device would over that time.
+    /// A stopped source drops them, like a closed device. Keep each step within the
+    /// recorder's ring buffer, two seconds.
+    pub fn advance(&self, frames: usize) {
+        let mut feed = self.0.lock().unwrap();
+        let channels = feed.channels;
+        let start = (feed.delivered_frames * channels).min(feed.samples.len());
+        let end = ((feed.delivered_frames + frames) * channels).min(feed.samples.len());
+        let mut chunk = feed.samples[start..end].to_vec();
+        chunk.resize(frames * channels, 0.0);
+        feed.delivered_frames += frames;
+        if let Some(sink) = feed.sink.as_mut() {
+            sink.push(&chunk);
+        }
+    }
+}
+
+impl AudioSource for SignalSource {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn start(&mut self, sink: SampleSink) -> Result<()> {
+        self.feed.lock().unwrap().sink = Some(sink);
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        self.feed.lock().unwrap().sink = None;
+    }
+
+    fn is_running(&self) -> bool {
+        self.feed.lock().unwrap().sink.is_some()
+    }
+}
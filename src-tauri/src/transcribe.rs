@@ -1,14 +1,310 @@
-use whisper_rs::{FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters};
+use serde::Serialize;
+use whisper_rs::{
+    FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters, WhisperState,
+};
 
 use crate::error::{Error, Result};
+use crate::vocabulary::Vocabulary;
 
 /// Sample rate required by Whisper (16kHz)
 pub const WHISPER_SAMPLE_RATE: u32 = 16000;
 
+/// Threads used for language detection, Whisper's own default
+const DETECT_THREADS: usize = 4;
+
+/// Default and largest beam; whisper.cpp decodes at most `WHISPER_MAX_DECODERS` beams
+pub const DEFAULT_BEAM_SIZE: u32 = 5;
+pub const MAX_BEAM_SIZE: u32 = 8;
+
+/// Upper bound of the sampling temperature and its fallback increment
+pub const MAX_TEMPERATURE: f32 = 1.0;
+
+/// Allowed ranges of the fallback thresholds
+pub const MAX_ENTROPY_THRESHOLD: f32 = 10.0;
+pub const MIN_LOGPROB_THRESHOLD: f32 = -10.0;
+
+/// Every language Whisper can transcribe as (code, name), in Whisper's own order,
+/// so the index of a language is its Whisper language id
+const LANGUAGES: &[(&str, &str)] = &[
+    ("en", "English"),
+    ("zh", "Chinese"),
+    ("de", "German"),
+    ("es", "Spanish"),
+    ("ru", "Russian"),
+    ("ko", "Korean"),
+    ("fr", "French"),
+    ("ja", "Japanese"),
+    ("pt", "Portuguese"),
+    ("tr", "Turkish"),
+    ("pl", "Polish"),
+    ("ca", "Catalan"),
+    ("nl", "Dutch"),
+    ("ar", "Arabic"),
+    ("sv", "Swedish"),
+    ("it", "Italian"),
+    ("id", "Indonesian"),
+    ("hi", "Hindi"),
+    ("fi", "Finnish"),
+    ("vi", "Vietnamese"),
+    ("he", "Hebrew"),
+    ("uk", "Ukrainian"),
+    ("el", "Greek"),
+    ("ms", "Malay"),
+    ("cs", "Czech"),
+    ("ro", "Romanian"),
+    ("da", "Danish"),
+    ("hu", "Hungarian"),
+    ("ta", "Tamil"),
+    ("no", "Norwegian"),
+    ("th", "Thai"),
+    ("ur", "Urdu"),
+    ("hr", "Croatian"),
+    ("bg", "Bulgarian"),
+    ("lt", "Lithuanian"),
+    ("la", "Latin"),
+    ("mi", "Maori"),
+    ("ml", "Malayalam"),
+    ("cy", "Welsh"),
+    ("sk", "Slovak"),
+    ("te", "Telugu"),
+    ("fa", "Persian"),
+    ("lv", "Latvian"),
+    ("bn", "Bengali"),
+    ("sr", "Serbian"),
+    ("az", "Azerbaijani"),
+    ("sl", "Slovenian"),
+    ("kn", "Kannada"),
+    ("et", "Estonian"),
+    ("mk", "Macedonian"),
+    ("br", "Breton"),
+    ("eu", "Basque"),
+    ("is", "Icelandic"),
+    ("hy", "Armenian"),
+    ("ne", "Nepali"),
+    ("mn", "Mongolian"),
+    ("bs", "Bosnian"),
+    ("kk", "Kazakh"),
+    ("sq", "Albanian"),
+    ("sw", "Swahili"),
+    ("gl", "Galician"),
+    ("mr", "Marathi"),
+    ("pa", "Punjabi"),
+    ("si", "Sinhala"),
+    ("km", "Khmer"),
+    ("sn", "Shona"),
+    ("yo", "Yoruba"),
+    ("so", "Somali"),
+    ("af", "Afrikaans"),
+    ("oc", "Occitan"),
+    ("ka", "Georgian"),
+    ("be", "Belarusian"),
+    ("tg", "Tajik"),
+    ("sd", "Sindhi"),
+    ("gu", "Gujarati"),
+    ("am", "Amharic"),
+    ("yi", "Yiddish"),
+    ("lo", "Lao"),
+    ("uz", "Uzbek"),
+    ("fo", "Faroese"),
+    ("ht", "Haitian Creole"),
+    ("ps", "Pashto"),
+    ("tk", "Turkmen"),
+    ("nn", "Norwegian Nynorsk"),
+    ("mt", "Maltese"),
+    ("sa", "Sanskrit"),
+    ("lb", "Luxembourgish"),
+    ("my", "Myanmar"),
+    ("bo", "Tibetan"),
+    ("tl", "Tagalog"),
+    ("mg", "Malagasy"),
+    ("as", "Assamese"),
+    ("tt", "Tatar"),
+    ("haw", "Hawaiian"),
+    ("ln", "Lingala"),
+    ("ha", "Hausa"),
+    ("ba", "Bashkir"),
+    ("jw", "Javanese"),
+    ("su", "Sundanese"),
+    ("yue", "Cantonese"),
+];
+
+/// A language from the Whisper language table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Language {
+    /// Code passed to Whisper and stored in history, e.g. "en"
+    code: &'static str,
+    name: &'static str,
+}
+
+impl Language {
+    pub const ENGLISH: Self = Self {
+        code: "en",
+        name: "English",
+    };
+
+    /// Look up a language by its Whisper code.
+    pub fn from_code(code: &str) -> Option<Self> {
+        Self::all().find(|language| language.code == code)
+    }
+
+    /// All languages, in Whisper's order
+    pub fn all() -> impl Iterator<Item = Self> {
+        LANGUAGES.iter().map(|&(code, name)| Self { code, name })
+    }
+
+    pub fn code(self) -> &'static str {
+        self.code
+    }
+
+    pub fn name(self) -> &'static str {
+        self.name
+    }
+}
+
+/// The language a hotkey transcribes as: a fixed one, or whatever Whisper detects
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LanguageChoice {
+    Fixed(Language),
+    Auto,
+}
+
+impl LanguageChoice {
+    /// Code of an automatic choice in settings and spill file names
+    pub const AUTO_CODE: &'static str = "auto";
+
+    /// Parse a language code, or "auto".
+    pub fn from_code(code: &str) -> Option<Self> {
+        if code == Self::AUTO_CODE {
+            Some(Self::Auto)
+        } else {
+            Language::from_code(code).map(Self::Fixed)
+        }
+    }
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Self::Fixed(language) => language.code(),
+            Self::Auto => Self::AUTO_CODE,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Fixed(language) => language.name(),
+            Self::Auto => "Auto-detect",
+        }
+    }
+}
+
+/// How Whisper decodes: the sampling strategy, and the temperature fallback that
+/// retries a window whose text looks unreliable by the thresholds below
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DecodingSettings {
+    /// Beam search with this many beams; greedy decoding with None
+    pub beam_size: Option<u32>,
+    /// Temperature of the first attempt; 0.0 always picks the likeliest token
+    pub temperature: f32,
+    /// Added to the temperature on each fallback attempt; 0.0 disables fallback
+    pub temperature_inc: f32,
+    /// Fall back when the text's entropy is below this, i.e. it repeats itself
+    pub entropy_threshold: f32,
+    /// Fall back when the average token log probability is below this
+    pub logprob_threshold: f32,
+    /// Drop a window as silence when its no-speech probability is above this
+    pub no_speech_threshold: f32,
+    /// Don't feed the text of the previous window back as context
+    pub no_context: bool,
+    /// Put all text in one segment
+    pub single_segment: bool,
+    /// Don't let a window start with a blank
+    pub suppress_blank: bool,
+}
+
+impl DecodingSettings {
+    /// Greedy decoding with whisper.cpp's default thresholds
+    pub const FAST: Self = Self {
+        beam_size: None,
+        temperature: 0.0,
+        temperature_inc: 0.2,
+        entropy_threshold: 2.4,
+        logprob_threshold: -1.0,
+        no_speech_threshold: 0.6,
+        no_context: true,
+        single_segment: false,
+        suppress_blank: true,
+    };
+
+    /// Beam search over five beams, as in OpenAI's reference implementation
+    pub const ACCURATE: Self = Self {
+        beam_size: Some(DEFAULT_BEAM_SIZE),
+        ..Self::FAST
+    };
+
+    /// Clamp every value into the range Whisper accepts.
+    #[must_use]
+    pub fn validated(self) -> Self {
+        Self {
+            beam_size: self.beam_size.map(|size| size.clamp(1, MAX_BEAM_SIZE)),
+            temperature: self.temperature.clamp(0.0, MAX_TEMPERATURE),
+            temperature_inc: self.temperature_inc.clamp(0.0, MAX_TEMPERATURE),
+            entropy_threshold: self.entropy_threshold.clamp(0.0, MAX_ENTROPY_THRESHOLD),
+            logprob_threshold: self.logprob_threshold.clamp(MIN_LOGPROB_THRESHOLD, 0.0),
+            no_speech_threshold: self.no_speech_threshold.clamp(0.0, 1.0),
+            ..self
+        }
+    }
+
+    fn apply(&self, params: &mut FullParams) {
+        params.set_temperature(self.temperature);
+        params.set_temperature_inc(self.temperature_inc);
+        params.set_entropy_thold(self.entropy_threshold);
+        params.set_logprob_thold(self.logprob_threshold);
+        params.set_no_speech_thold(self.no_speech_threshold);
+        params.set_no_context(self.no_context);
+        params.set_single_segment(self.single_segment);
+        params.set_suppress_blank(self.suppress_blank);
+    }
+
+    fn strategy(&self) -> SamplingStrategy {
+        match self.beam_size {
+            Some(beam_size) => SamplingStrategy::BeamSearch {
+                beam_size: i32::try_from(beam_size).unwrap_or(1),
+                // Negative means whisper.cpp's default
+                patience: -1.0,
+            },
+            None => SamplingStrategy::Greedy { best_of: 1 },
+        }
+    }
+}
+
+impl Default for DecodingSettings {
+    fn default() -> Self {
+        Self::FAST
+    }
+}
+
+/// How to transcribe a recording
 #[derive(Debug, Clone, Copy)]
-pub enum Language {
-    English,
-    German,
+pub struct TranscribeOptions<'a> {
+    pub language: LanguageChoice,
+    /// Languages an automatic choice picks from; empty allows every language
+    pub detect_among: &'a [Language],
+    /// Translate the speech to English instead of transcribing it
+    pub translate: bool,
+    /// Terms passed to Whisper as the initial prompt
+    pub vocabulary: &'a Vocabulary,
+    pub decoding: DecodingSettings,
+}
+
+/// Text of a transcription and the language it was spoken in
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transcript {
+    pub text: String,
+    pub language: Language,
+    /// How likely the language is according to Whisper, if it was detected
+    pub probability: Option<f32>,
+    /// Whether the text is an English translation of the speech
+    pub translated: bool,
 }
 
 pub struct Transcriber {
@@ -53,30 +349,61 @@ impl Transcriber {
         Ok(())
     }
 
-    pub fn transcribe(&self, audio: &[f32], language: Language) -> Result<String> {
+    /// Transcribe `audio`. An automatic language choice picks the most likely of
+    /// `options.detect_among`, or of every language if it is empty.
+    pub fn transcribe(&self, audio: &[f32], options: &TranscribeOptions) -> Result<Transcript> {
+        self.transcribe_with_progress(audio, options, None::<fn(i32)>)
+    }
+
+    /// Transcribe, reporting progress in percent through `on_progress`.
+    pub fn transcribe_with_progress<F>(
+        &self,
+        audio: &[f32],
+        options: &TranscribeOptions,
+        on_progress: Option<F>,
+    ) -> Result<Transcript>
+    where
+        F: FnMut(i32) + 'static,
+    {
+        let (language, probability) = match options.language {
+            LanguageChoice::Fixed(language) => (language, None),
+            // Nothing to detect from; the result is empty anyway
+            LanguageChoice::Auto if audio.is_empty() => (Language::ENGLISH, None),
+            LanguageChoice::Auto => {
+                let (language, probability) = self.detect_language(audio, options.detect_among)?;
+                (language, Some(probability))
+            }
+        };
+        let mut transcript = Transcript {
+            text: String::new(),
+            language,
+            probability,
+            translated: options.translate,
+        };
         if audio.is_empty() {
-            return Ok(String::new());
+            return Ok(transcript);
         }
 
-        let mut state = self
-            .ctx
-            .create_state()
-            .map_err(|e| Error::Transcription(format!("failed to create state: {e}")))?;
+        let mut state = self.create_state()?;
 
-        let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
+        let mut params = FullParams::new(options.decoding.strategy());
+        options.decoding.apply(&mut params);
 
-        // Set language
-        let lang_key = match language {
-            Language::English => "en",
-            Language::German => "de",
-        };
-        params.set_language(Some(lang_key));
+        params.set_language(Some(language.code()));
+        // Whisper translates from the source language above; it only translates to English
+        params.set_translate(options.translate);
+
+        let prompt = self.initial_prompt(&options.vocabulary.terms_for(language));
+        if !prompt.is_empty() {
+            params.set_initial_prompt(&prompt);
+        }
 
         // Suppress console output
         params.set_print_special(false);
         params.set_print_progress(false);
         params.set_print_realtime(false);
         params.set_print_timestamps(false);
+        params.set_progress_callback_safe(on_progress);
 
         state
             .full(params, audio)
@@ -94,6 +421,140 @@ impl Transcriber {
             }
         }
 
-        Ok(result.trim().to_string())
+        transcript.text = result.trim().to_string();
+        Ok(transcript)
+    }
+
+    /// Detect the spoken language from the first 30 seconds of `audio`, restricted
+    /// to `candidates` unless it is empty. Returns the language and its probability.
+    fn detect_language(&self, audio: &[f32], candidates: &[Language]) -> Result<(Language, f32)> {
+        let mut state = self.create_state()?;
+        state
+            .pcm_to_mel(audio, DETECT_THREADS)
+            .map_err(|e| Error::Transcription(format!("failed to compute spectrogram: {e}")))?;
+        let (_, probabilities) = state
+            .lang_detect(0, DETECT_THREADS)
+            .map_err(|e| Error::Transcription(format!("language detection failed: {e}")))?;
+
+        let (language, probability) = most_likely_language(&probabilities, candidates)
+            .ok_or_else(|| Error::Transcription("no language detected".to_string()))?;
+        eprintln!(
+            "[Detected {} ({:.0}%)]",
+            language.name(),
+            probability * 100.0
+        );
+        Ok((language, probability))
+    }
+
+    /// Join vocabulary terms into an initial prompt. Whisper only keeps the last half
+    /// context of prompt tokens, so terms that would push earlier ones out are left off.
+    fn initial_prompt(&self, terms: &[&str]) -> String {
+        let max_tokens = usize::try_from(self.ctx.n_text_ctx() / 2).unwrap_or(0);
+        let mut prompt = String::new();
+        for (i, term) in terms.iter().enumerate() {
+            let candidate = if prompt.is_empty() {
+                (*term).to_string()
+            } else {
+                format!("{prompt}, {term}")
+            };
+            // Tokens merge across the separators, so the joined prompt is what counts
+            let fits = self
+                .ctx
+                .tokenize(&candidate, max_tokens)
+                .is_ok_and(|tokens| tokens.len() < max_tokens);
+            if !fits {
+                eprintln!(
+                    "[Vocabulary exceeds the prompt limit, leaving out {} terms]",
+                    terms.len() - i
+                );
+                break;
+            }
+            prompt = candidate;
+        }
+        prompt
+    }
+
+    fn create_state(&self) -> Result<WhisperState> {
+        self.ctx
+            .create_state()
+            .map_err(|e| Error::Transcription(format!("failed to create state: {e}")))
+    }
+}
+
+/// The candidate Whisper rates most likely, given its probabilities indexed by
+/// language id. An empty candidate list allows every language.
+fn most_likely_language(probabilities: &[f32], candidates: &[Language]) -> Option<(Language, f32)> {
+    Language::all()
+        .zip(probabilities.iter().copied())
+        .filter(|(language, _)| candidates.is_empty() || candidates.contains(language))
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_language_codes_are_unique() {
+        let codes: HashSet<_> = Language::all().map(Language::code).collect();
+        assert_eq!(codes.len(), LANGUAGES.len());
+
+        let german = Language::from_code("de").unwrap();
+        assert_eq!(german.name(), "German");
+        assert_eq!(Language::from_code("en"), Some(Language::ENGLISH));
+        assert!(Language::from_code("xx").is_none());
+    }
+
+    #[test]
+    fn test_detection_is_restricted_to_candidates() {
+        let german = Language::from_code("de").unwrap();
+        let dutch = Language::from_code("nl").unwrap();
+        let mut probabilities = vec![0.0; LANGUAGES.len()];
+        probabilities[0] = 0.2;
+        probabilities[2] = 0.3;
+        probabilities[12] = 0.5;
+
+        assert_eq!(
+            most_likely_language(&probabilities, &[]),
+            Some((dutch, 0.5))
+        );
+        assert_eq!(
+            most_likely_language(&probabilities, &[Language::ENGLISH, german]),
+            Some((german, 0.3))
+        );
+        assert_eq!(
+            LanguageChoice::from_code("auto"),
+            Some(LanguageChoice::Auto)
+        );
+    }
+
+    #[test]
+    fn test_decoding_settings_are_clamped() {
+        assert_eq!(
+            DecodingSettings::ACCURATE.validated(),
+            DecodingSettings::ACCURATE
+        );
+
+        let decoding = DecodingSettings {
+            beam_size: Some(0),
+            temperature: 2.0,
+            temperature_inc: -0.2,
+            logprob_threshold: 1.0,
+            no_speech_threshold: 1.5,
+            ..DecodingSettings::FAST
+        }
+        .validated();
+        assert_eq!(decoding.beam_size, Some(1));
+        assert!((decoding.temperature - MAX_TEMPERATURE).abs() < f32::EPSILON);
+        assert!(decoding.temperature_inc.abs() < f32::EPSILON);
+        assert!(decoding.logprob_threshold.abs() < f32::EPSILON);
+        assert!((decoding.no_speech_threshold - 1.0).abs() < f32::EPSILON);
+
+        let decoding = DecodingSettings {
+            beam_size: Some(32),
+            ..DecodingSettings::FAST
+        };
+        assert_eq!(decoding.validated().beam_size, Some(MAX_BEAM_SIZE));
     }
 }
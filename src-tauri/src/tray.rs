@@ -9,7 +9,10 @@ use crate::settings::RecordingState;
 
 pub const TRAY_ID: &str = "main";
 
-pub fn create_tray<R: Runtime>(app: &AppHandle<R>, hotkey_en: &str) -> tauri::Result<TrayIcon<R>> {
+pub fn create_tray<R: Runtime>(
+    app: &AppHandle<R>,
+    hotkey_record: &str,
+) -> tauri::Result<TrayIcon<R>> {
     let open_i = MenuItem::with_id(app, "open", "Open Scribe", true, None::<&str>)?;
     let quit_i = MenuItem::with_id(app, "quit", "Quit Scribe", true, None::<&str>)?;
     let menu = Menu::with_items(app, &[&open_i, &quit_i])?;
@@ -17,7 +20,7 @@ pub fn create_tray<R: Runtime>(app: &AppHandle<R>, hotkey_en: &str) -> tauri::Re
     let tray = TrayIconBuilder::with_id(TRAY_ID)
         .icon(load_tray_icon(RecordingState::Idle)?)
         .menu(&menu)
-        .tooltip(format!("Scribe - Ready (Press {hotkey_en} to record)"))
+        .tooltip(format!("Scribe - Ready (Press {hotkey_record} to record)"))
         .show_menu_on_left_click(false)
         .on_menu_event(|app, event| match event.id.as_ref() {
             "open" => {
@@ -67,11 +70,11 @@ pub fn load_tray_icon(state: RecordingState) -> tauri::Result<Image<'static>> {
 pub fn update_tray_state<R: Runtime>(
     tray: &TrayIcon<R>,
     state: RecordingState,
-    hotkey_en: &str,
+    hotkey_record: &str,
     hotkey_mute: &str,
 ) -> tauri::Result<()> {
     let tooltip = match state {
-        RecordingState::Idle => format!("Scribe - Ready (Press {hotkey_en} to record)"),
+        RecordingState::Idle => format!("Scribe - Ready (Press {hotkey_record} to record)"),
         RecordingState::Recording => "Scribe - Recording...".to_string(),
         RecordingState::Transcribing => "Scribe - Transcribing...".to_string(),
         RecordingState::Muted => format!("Scribe - Muted (Press {hotkey_mute} to unmute)"),
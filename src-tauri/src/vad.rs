@@ -0,0 +1,120 @@
+//! Energy-based voice activity detection used to trim silence before transcription.
+
+use std::ops::Range;
+
+use crate::transcribe::WHISPER_SAMPLE_RATE;
+
+/// Analysis frame length (30ms at 16kHz)
+const FRAME_LEN: usize = (WHISPER_SAMPLE_RATE as usize * 30) / 1000;
+
+/// Absolute RMS floor below which a frame is never considered speech
+const MIN_SPEECH_RMS: f32 = 0.01;
+
+/// Highest noise floor assumed. Audio without pauses, e.g. continuous speech or system
+/// audio, has no quiet frames to measure, and its floor would reject the speech itself.
+const MAX_NOISE_FLOOR: f32 = 0.02;
+
+/// A frame counts as speech if its RMS exceeds the noise floor by this factor
+const NOISE_FLOOR_RATIO: f32 = 3.0;
+
+/// Minimum number of speech frames (~150ms) for a clip to count as speech
+const MIN_SPEECH_FRAMES: usize = 5;
+
+/// Audio kept before the first and after the last speech frame (~250ms)
+const PADDING_SAMPLES: usize = (WHISPER_SAMPLE_RATE as usize * 250) / 1000;
+
+/// Find the range of 16kHz mono samples that contains speech.
+/// Returns None if the clip contains no speech at all.
+pub fn detect_speech(samples: &[f32]) -> Option<Range<usize>> {
+    let energies: Vec<f32> = samples.chunks(FRAME_LEN).map(frame_rms).collect();
+    if energies.is_empty() {
+        return None;
+    }
+
+    let floor = noise_floor(&energies).min(MAX_NOISE_FLOOR);
+    let threshold = (floor * NOISE_FLOOR_RATIO).max(MIN_SPEECH_RMS);
+    let is_speech = |rms: &f32| *rms > threshold;
+
+    if energies.iter().filter(|rms| is_speech(rms)).count() < MIN_SPEECH_FRAMES {
+        return None;
+    }
+
+    let first = energies.iter().position(is_speech)?;
+    let last = energies.iter().rposition(is_speech)?;
+
+    let start = (first * FRAME_LEN).saturating_sub(PADDING_SAMPLES);
+    let end = ((last + 1) * FRAME_LEN + PADDING_SAMPLES).min(samples.len());
+    Some(start..end)
+}
+
+/// Trim leading and trailing non-speech from 16kHz mono samples.
+/// Returns None if the clip contains no speech at all.
+pub fn trim_silence(samples: &[f32]) -> Option<&[f32]> {
+    detect_speech(samples).map(|range| &samples[range])
+}
+
+fn frame_rms(frame: &[f32]) -> f32 {
+    let sum_squares: f32 = frame.iter().map(|s| s * s).sum();
+    #[allow(clippy::cast_precision_loss)]
+    let rms = (sum_squares / frame.len() as f32).sqrt();
+    rms
+}
+
+/// Estimate the background noise level as the 10th percentile of frame energies.
+fn noise_floor(energies: &[f32]) -> f32 {
+    let mut sorted = energies.to_vec();
+    sorted.sort_by(f32::total_cmp);
+    sorted[sorted.len() / 10]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_signal::sine_wave;
+
+    #[test]
+    fn test_silence_has_no_speech() {
+        let silence = vec![0.0f32; WHISPER_SAMPLE_RATE as usize * 2];
+        assert!(detect_speech(&silence).is_none());
+        assert!(detect_speech(&[]).is_none());
+    }
+
+    #[test]
+    fn test_short_click_has_no_speech() {
+        // A 30ms burst, e.g. the key press itself, is not speech
+        let mut samples = vec![0.0f32; WHISPER_SAMPLE_RATE as usize];
+        samples.splice(
+            8000..8000 + FRAME_LEN,
+            sine_wave(220.0, 0.5, WHISPER_SAMPLE_RATE, FRAME_LEN),
+        );
+        assert!(detect_speech(&samples).is_none());
+    }
+
+    #[test]
+    fn test_trims_leading_and_trailing_silence() {
+        // 1s silence, 1s tone, 1s silence
+        let second = WHISPER_SAMPLE_RATE as usize;
+        let mut samples = vec![0.0f32; second];
+        samples.extend(sine_wave(220.0, 0.3, WHISPER_SAMPLE_RATE, second));
+        samples.extend(vec![0.0f32; second]);
+
+        let range = detect_speech(&samples).unwrap();
+        assert!(range.start <= second && range.start >= second - PADDING_SAMPLES - FRAME_LEN);
+        assert!(range.end >= 2 * second && range.end <= 2 * second + PADDING_SAMPLES + FRAME_LEN);
+
+        let trimmed = trim_silence(&samples).unwrap();
+        assert_eq!(trimmed.len(), range.len());
+    }
+
+    #[test]
+    fn test_speech_without_pauses_is_kept() {
+        // No quiet frames to measure the noise floor from, e.g. pre-roll off
+        let samples = sine_wave(
+            220.0,
+            0.3,
+            WHISPER_SAMPLE_RATE,
+            WHISPER_SAMPLE_RATE as usize * 2,
+        );
+        assert_eq!(detect_speech(&samples), Some(0..samples.len()));
+    }
+}
@@ -0,0 +1,194 @@
+//! User vocabulary: product names, people and identifiers Whisper should spell right.
+//! The terms are passed to Whisper as the initial prompt, see `Transcriber::transcribe`.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+use crate::transcribe::Language;
+
+/// Longest term accepted; vocabulary is meant for names, not sentences
+pub const MAX_TERM_CHARS: usize = 64;
+
+/// Terms for every language, and terms for one language by language code
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Vocabulary {
+    global: Vec<String>,
+    languages: BTreeMap<String, Vec<String>>,
+}
+
+impl Vocabulary {
+    /// Parse the `vocabulary` setting: `{ "global": [..], "languages": { "<code>": [..] } }`.
+    /// Invalid terms and unknown languages are skipped.
+    pub fn from_value(value: &serde_json::Value) -> Self {
+        let mut vocabulary = Self::default();
+        let terms = |value: Option<&serde_json::Value>| -> Vec<String> {
+            value
+                .and_then(|v| v.as_array())
+                .map(|terms| {
+                    terms
+                        .iter()
+                        .filter_map(|term| term.as_str())
+                        .map(String::from)
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+
+        for term in terms(value.get("global")) {
+            let _ = vocabulary.add(&term, None);
+        }
+        if let Some(languages) = value.get("languages").and_then(|v| v.as_object()) {
+            for (code, language_terms) in languages {
+                let Some(language) = Language::from_code(code) else {
+                    continue;
+                };
+                for term in terms(Some(language_terms)) {
+                    let _ = vocabulary.add(&term, Some(language));
+                }
+            }
+        }
+        vocabulary
+    }
+
+    /// The vocabulary in the format of the `vocabulary` setting
+    pub fn to_value(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap_or(serde_json::Value::Null)
+    }
+
+    /// Add a term for `language`, or for every language with None.
+    /// Returns false if the list already has it.
+    pub fn add(&mut self, term: &str, language: Option<Language>) -> Result<bool> {
+        let term = term.trim();
+        if term.is_empty() {
+            return Err(Error::Vocabulary("term is empty".to_string()));
+        }
+        if term.chars().count() > MAX_TERM_CHARS {
+            return Err(Error::Vocabulary(format!(
+                "\"{term}\" is longer than {MAX_TERM_CHARS} characters"
+            )));
+        }
+        // Whisper takes the prompt as a C string
+        if term.chars().any(char::is_control) {
+            return Err(Error::Vocabulary(format!(
+                "\"{term}\" contains control characters"
+            )));
+        }
+
+        let terms = match language {
+            Some(language) => self
+                .languages
+                .entry(language.code().to_string())
+                .or_default(),
+            None => &mut self.global,
+        };
+        if terms.iter().any(|t| t.eq_ignore_ascii_case(term)) {
+            return Ok(false);
+        }
+        terms.push(term.to_string());
+        Ok(true)
+    }
+
+    /// Remove a term from the list of `language`, or the global list with None.
+    /// Returns false if it wasn't there.
+    pub fn remove(&mut self, term: &str, language: Option<Language>) -> bool {
+        let terms = match language {
+            Some(language) => match self.languages.get_mut(language.code()) {
+                Some(terms) => terms,
+                None => return false,
+            },
+            None => &mut self.global,
+        };
+        let before = terms.len();
+        let term = term.trim();
+        terms.retain(|t| !t.eq_ignore_ascii_case(term));
+        let removed = terms.len() < before;
+
+        if let Some(language) = language {
+            if self
+                .languages
+                .get(language.code())
+                .is_some_and(Vec::is_empty)
+            {
+                self.languages.remove(language.code());
+            }
+        }
+        removed
+    }
+
+    /// Add every term in `text`: one per line or separated by commas or semicolons,
+    /// with `#` starting a comment line. Returns how many terms were new.
+    pub fn import(&mut self, text: &str, language: Option<Language>) -> Result<usize> {
+        let mut added = 0;
+        for term in text
+            .lines()
+            .filter(|line| !line.trim_start().starts_with('#'))
+            .flat_map(|line| line.split([',', ';']))
+            .map(str::trim)
+            .filter(|term| !term.is_empty())
+        {
+            if self.add(term, language)? {
+                added += 1;
+            }
+        }
+        Ok(added)
+    }
+
+    /// Terms for a transcription in `language`: its own terms first, then the global ones
+    pub fn terms_for(&self, language: Language) -> Vec<&str> {
+        let own = self.languages.get(language.code()).into_iter().flatten();
+        let mut terms: Vec<&str> = Vec::new();
+        for term in own.chain(&self.global) {
+            if !terms.iter().any(|t| t.eq_ignore_ascii_case(term)) {
+                terms.push(term);
+            }
+        }
+        terms
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_terms_for_language_come_first() {
+        let german = Language::from_code("de").unwrap();
+        let mut vocabulary = Vocabulary::default();
+        assert!(vocabulary.add("Scribe", None).unwrap());
+        assert!(vocabulary.add(" Kubernetes ", None).unwrap());
+        assert!(vocabulary.add("Grüßle", Some(german)).unwrap());
+        // Case-insensitive duplicates aren't added twice
+        assert!(!vocabulary.add("scribe", None).unwrap());
+        assert!(vocabulary.add("", None).is_err());
+        assert!(vocabulary.add("line\nbreak", None).is_err());
+
+        assert_eq!(
+            vocabulary.terms_for(german),
+            vec!["Grüßle", "Scribe", "Kubernetes"]
+        );
+        assert_eq!(
+            vocabulary.terms_for(Language::ENGLISH),
+            vec!["Scribe", "Kubernetes"]
+        );
+
+        assert!(vocabulary.remove("Grüßle", Some(german)));
+        assert!(!vocabulary.remove("Grüßle", Some(german)));
+        assert_eq!(vocabulary.terms_for(german), vec!["Scribe", "Kubernetes"]);
+        // Removing matches case-insensitively, like adding
+        assert!(vocabulary.remove("kubernetes", None));
+        assert_eq!(vocabulary.terms_for(german), vec!["Scribe"]);
+    }
+
+    #[test]
+    fn test_import_splits_lines_and_commas() {
+        let mut vocabulary = Vocabulary::default();
+        let text = "# Product names\nScribe, Whisper\n\nrusqlite; cpal\nScribe\n";
+        assert_eq!(vocabulary.import(text, None).unwrap(), 4);
+        assert_eq!(
+            vocabulary.terms_for(Language::ENGLISH),
+            vec!["Scribe", "Whisper", "rusqlite", "cpal"]
+        );
+    }
+}
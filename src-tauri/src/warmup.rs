@@ -24,16 +24,21 @@ pub fn spawn_warmup(app: &tauri::AppHandle, transcriber: Arc<Transcriber>) {
         let start_time = Instant::now();
 
         // Set state to WarmingUp and get hotkeys
-        let (hotkey_en, hotkey_mute) = {
+        let (hotkey_record, hotkey_mute) = {
             let resources = app_handle.state::<Arc<Mutex<AppResources>>>();
             let res = resources.lock().unwrap();
             res.state.set(RecordingState::WarmingUp);
-            (res.hotkey_en.clone(), res.hotkey_mute.clone())
+            (res.hotkey_record.clone(), res.hotkey_mute.clone())
         };
 
         // Update tray
         if let Some(tray) = app_handle.tray_by_id(TRAY_ID) {
-            let _ = update_tray_state(&tray, RecordingState::WarmingUp, &hotkey_en, &hotkey_mute);
+            let _ = update_tray_state(
+                &tray,
+                RecordingState::WarmingUp,
+                &hotkey_record,
+                &hotkey_mute,
+            );
         }
 
         // Show overlay with warmup mode
@@ -75,7 +80,7 @@ fn hide_warmup_overlay(app: &tauri::AppHandle) {
         let _ = overlay.hide();
     }
 
-    let (final_state, hotkey_en, hotkey_mute) = {
+    let (final_state, hotkey_record, hotkey_mute) = {
         let resources = app.state::<Arc<Mutex<AppResources>>>();
         let res = resources.lock().unwrap();
         let state = if res.state.get() == RecordingState::WarmingUp {
@@ -84,10 +89,10 @@ fn hide_warmup_overlay(app: &tauri::AppHandle) {
         } else {
             res.state.get()
         };
-        (state, res.hotkey_en.clone(), res.hotkey_mute.clone())
+        (state, res.hotkey_record.clone(), res.hotkey_mute.clone())
     };
 
     if let Some(tray) = app.tray_by_id(TRAY_ID) {
-        let _ = update_tray_state(&tray, final_state, &hotkey_en, &hotkey_mute);
+        let _ = update_tray_state(&tray, final_state, &hotkey_record, &hotkey_mute);
     }
 }
@@ -21,12 +21,192 @@ export const DEFAULT_HOTKEYS = {
  * Store keys for persisting settings
  */
 export const STORE_KEYS = {
-  HOTKEY: "hotkey",
-  HOTKEY_DE: "hotkey_de",
+  LANGUAGE_HOTKEYS: "language_hotkeys",
+  DETECT_LANGUAGES: "detect_languages",
   HOTKEY_MUTE: "hotkey_mute",
   MODEL_PATH: "model_path",
   AUDIO_DEVICE: "audio_device",
   OUTPUT_MODE: "output_mode",
+  INPUT_GAIN: "input_gain",
+  PREROLL_MS: "preroll_ms",
+  INPUT_CHANNELS: "input_channels",
+  DSP_HIGH_PASS: "dsp_high_pass",
+  DSP_NOISE_GATE: "dsp_noise_gate",
+  DSP_DENOISE: "dsp_denoise",
+  MAX_RECORDING_SECS: "max_recording_secs",
+  SAVE_AUDIO: "save_audio",
+  AUDIO_QUOTA_MB: "audio_quota_mb",
+  PLAYBACK_DEVICE: "playback_device",
+  METER_RATE_HZ: "meter_rate_hz",
+  CAPTURE_SAMPLE_RATE: "capture_sample_rate",
+  CAPTURE_SAMPLE_FORMAT: "capture_sample_format",
+  CAPTURE_BUFFER_SIZE: "capture_buffer_size",
+  LOOPBACK_DEVICE: "loopback_device",
+  MIX_MIC_GAIN: "mix_mic_gain",
+  MIX_LOOPBACK_GAIN: "mix_loopback_gain",
+  TRAILING_CAPTURE_MS: "trailing_capture_ms",
+  MIN_PRESS_MS: "min_press_ms",
+  DECODING_PRESET: "decoding_preset",
+  DECODING_BEAM_SEARCH: "decoding_beam_search",
+  DECODING_BEAM_SIZE: "decoding_beam_size",
+  DECODING_TEMPERATURE: "decoding_temperature",
+  DECODING_TEMPERATURE_INC: "decoding_temperature_inc",
+  DECODING_ENTROPY_THRESHOLD: "decoding_entropy_threshold",
+  DECODING_LOGPROB_THRESHOLD: "decoding_logprob_threshold",
+  DECODING_NO_SPEECH_THRESHOLD: "decoding_no_speech_threshold",
+  DECODING_NO_CONTEXT: "decoding_no_context",
+  DECODING_SINGLE_SEGMENT: "decoding_single_segment",
+  DECODING_SUPPRESS_BLANK: "decoding_suppress_blank",
+} as const;
+
+/**
+ * What a recording hotkey captures
+ */
+export const CAPTURE_MODES = {
+  microphone: "Microphone",
+  loopback: "System audio",
+  mix: "Microphone + system audio",
+} as const;
+
+export type CaptureMode = keyof typeof CAPTURE_MODES;
+
+/**
+ * Language code that lets Whisper detect the spoken language
+ */
+export const AUTO_LANGUAGE = "auto";
+
+/**
+ * A push-to-talk hotkey, the language it transcribes (a code or AUTO_LANGUAGE) and what it records
+ */
+export interface LanguageHotkey {
+  hotkey: string;
+  language: string;
+  /** Type the speech translated to English */
+  translate: boolean;
+  capture: CaptureMode;
+}
+
+/**
+ * Whisper decoding presets; custom uses the values set in settings
+ */
+export const DECODING_PRESETS = {
+  fast: "Fast",
+  accurate: "Accurate",
+  custom: "Custom",
+} as const;
+
+export type DecodingPreset = keyof typeof DECODING_PRESETS;
+
+/**
+ * How Whisper decodes, see `DecodingSettings` in the backend
+ */
+export interface DecodingSettings {
+  beam_search: boolean;
+  beam_size: number;
+  temperature: number;
+  temperature_inc: number;
+  entropy_threshold: number;
+  logprob_threshold: number;
+  no_speech_threshold: number;
+  no_context: boolean;
+  single_segment: boolean;
+  suppress_blank: boolean;
+}
+
+/**
+ * Values of the presets, matching the backend
+ */
+export const FAST_DECODING: DecodingSettings = {
+  beam_search: false,
+  beam_size: 5,
+  temperature: 0,
+  temperature_inc: 0.2,
+  entropy_threshold: 2.4,
+  logprob_threshold: -1,
+  no_speech_threshold: 0.6,
+  no_context: true,
+  single_segment: false,
+  suppress_blank: true,
+};
+
+export const ACCURATE_DECODING: DecodingSettings = { ...FAST_DECODING, beam_search: true };
+
+/**
+ * Valid ranges of the custom decoding values; the backend clamps anything outside
+ */
+export const DECODING_LIMITS = {
+  beam_size: { MIN: 1, MAX: 8, STEP: 1 },
+  temperature: { MIN: 0, MAX: 1, STEP: 0.1 },
+  temperature_inc: { MIN: 0, MAX: 1, STEP: 0.1 },
+  entropy_threshold: { MIN: 0, MAX: 10, STEP: 0.1 },
+  logprob_threshold: { MIN: -10, MAX: 0, STEP: 0.1 },
+  no_speech_threshold: { MIN: 0, MAX: 1, STEP: 0.05 },
+} as const;
+
+/**
+ * Gain range of each source when mixing microphone and system audio
+ */
+export const MIX_GAIN = {
+  MIN: 0,
+  MAX: 4,
+  STEP: 0.1,
+  DEFAULT: 1,
+} as const;
+
+/**
+ * Selectable pre-roll lengths (audio kept from before the hotkey is pressed)
+ */
+export const PREROLL_OPTIONS_MS = [0, 300, 500, 750, 1000] as const;
+export const DEFAULT_PREROLL_MS = 500;
+
+/**
+ * Selectable lengths of audio kept after the hotkey is released
+ */
+export const TRAILING_OPTIONS_MS = [0, 100, 150, 300, 500, 1000] as const;
+export const DEFAULT_TRAILING_MS = 150;
+
+/**
+ * Selectable minimum hotkey presses; shorter taps are discarded
+ */
+export const MIN_PRESS_OPTIONS_MS = [0, 100, 200, 300, 500] as const;
+export const DEFAULT_MIN_PRESS_MS = 200;
+
+/**
+ * Selectable capture buffer sizes in frames, limited to what the device supports
+ */
+export const BUFFER_SIZE_OPTIONS_FRAMES = [64, 128, 256, 512, 1024, 2048] as const;
+
+/**
+ * Sample rate Whisper works at; capturing at this rate skips resampling
+ */
+export const WHISPER_SAMPLE_RATE = 16000;
+
+/**
+ * Selectable overlay meter update rates (readings per second)
+ */
+export const METER_RATE_OPTIONS_HZ = [10, 20, 30, 60] as const;
+export const DEFAULT_METER_RATE_HZ = 20;
+
+/**
+ * Selectable maximum recording lengths in seconds, after which recording auto-stops
+ */
+export const MAX_RECORDING_OPTIONS_SECS = [60, 120, 300, 600, 1800] as const;
+export const DEFAULT_MAX_RECORDING_SECS = 300;
+
+/**
+ * Selectable storage limits for saved recordings (16kHz WAV is ~1.9 MB per minute)
+ */
+export const AUDIO_QUOTA_OPTIONS_MB = [100, 250, 500, 1000, 2000] as const;
+export const DEFAULT_AUDIO_QUOTA_MB = 500;
+
+/**
+ * Manual input gain range and default
+ */
+export const INPUT_GAIN = {
+  MIN: 0.5,
+  MAX: 10,
+  STEP: 0.5,
+  DEFAULT: 3,
 } as const;
 
 /**
@@ -46,6 +226,8 @@ export const ICON_NAMES = [
   "error",
   "microphone",
   "refresh",
+  "play",
+  "pause",
 ] as const;
 
 /**
@@ -53,36 +235,14 @@ export const ICON_NAMES = [
  */
 export type IconName = typeof ICON_NAMES[number];
 
-/**
- * Supported transcription languages
- */
-export const LANGUAGES = {
-  en: {
-    code: "en",
-    label: "EN",
-    name: "English",
-  },
-  de: {
-    code: "de",
-    label: "DE",
-    name: "German",
-  },
-} as const;
-
-export type LanguageCode = keyof typeof LANGUAGES;
-
 /**
  * Get display label for a language code
  */
 export function getLanguageLabel(code: string): string {
-  const lang = LANGUAGES[code as LanguageCode];
-  return lang?.label ?? code.toUpperCase();
+  return code.toUpperCase();
 }
 
 /**
- * Get full name for a language code
+ * Audio file formats accepted by "Transcribe file"
  */
-export function getLanguageName(code: string): string {
-  const lang = LANGUAGES[code as LanguageCode];
-  return lang?.name ?? code;
-}
+export const AUDIO_FILE_EXTENSIONS = ["wav", "flac", "mp3", "ogg"] as const;
@@ -0,0 +1,38 @@
+import { ref } from "vue";
+import { invoke } from "@tauri-apps/api/core";
+import { AUTO_LANGUAGE } from "../constants";
+
+/**
+ * A language Whisper can transcribe
+ */
+export interface Language {
+  code: string;
+  name: string;
+}
+
+// Global state: the table never changes, so it is fetched once
+const languages = ref<Language[]>([]);
+let loading: Promise<void> | null = null;
+
+export function useLanguages() {
+  function load(): Promise<void> {
+    loading ??= invoke<Language[]>("list_languages")
+      .then((list) => {
+        languages.value = list.sort((a, b) => a.name.localeCompare(b.name));
+      })
+      .catch((e) => {
+        console.error("Failed to load languages:", e);
+        loading = null;
+      });
+    return loading;
+  }
+
+  function languageName(code: string): string {
+    if (code === AUTO_LANGUAGE) {
+      return "Auto-detect";
+    }
+    return languages.value.find((l) => l.code === code)?.name ?? code;
+  }
+
+  return { languages, load, languageName };
+}
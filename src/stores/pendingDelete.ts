@@ -4,10 +4,13 @@ import { invoke } from "@tauri-apps/api/core";
 export interface Transcription {
   id: number;
   text: string;
+  /** Language that was spoken; the text is English if it was translated */
   language: string;
+  translated: boolean;
   duration_ms: number;
   word_count: number;
   created_at: string;
+  audio_path: string | null;
 }
 
 export interface PendingDelete {
@@ -0,0 +1,53 @@
+import { ref } from "vue";
+import { invoke } from "@tauri-apps/api/core";
+import { listen, type UnlistenFn } from "@tauri-apps/api/event";
+
+export interface PlaybackStatus {
+  id: number;
+  position_ms: number;
+  duration_ms: number;
+  playing: boolean;
+}
+
+// Global state: only one recording plays at a time
+const status = ref<PlaybackStatus | null>(null);
+let unlisten: UnlistenFn | null = null;
+let listeners = 0;
+
+export function usePlayback() {
+  async function start() {
+    listeners += 1;
+    if (!unlisten) {
+      unlisten = await listen<PlaybackStatus>("playback-position", (event) => {
+        // A stop reports zero duration; forget the recording
+        status.value = event.payload.duration_ms > 0 ? event.payload : null;
+      });
+    }
+  }
+
+  function stop() {
+    listeners -= 1;
+    if (listeners === 0 && unlisten) {
+      unlisten();
+      unlisten = null;
+    }
+  }
+
+  async function play(id: number) {
+    try {
+      await invoke("playback_play", { id });
+    } catch (e) {
+      console.error("Failed to play recording:", e);
+    }
+  }
+
+  async function pause() {
+    await invoke("playback_pause");
+  }
+
+  async function seek(positionMs: number) {
+    await invoke("playback_seek", { positionMs });
+  }
+
+  return { status, start, stop, play, pause, seek };
+}